@@ -1,70 +1,111 @@
-use std::{env, fs::{self, DirBuilder, File}, io::Write, path::Path};
+use std::{collections::HashMap, ops::RangeInclusive, path::{Path, PathBuf}};
 
-use crate::compiling::compiler::Compiler;
+use crate::compiling::compiler::{Compiler, ListingEntry};
 
-use self::{lexer::{scan_tokens, Token}, parser::{parse, program_node::ProgramNode}};
+use self::{error_handler::{CompilerError, ErrorCode}, lexer::scan_tokens, parser::{number_nodes::Imm16, parse}, sources::SourceProvider, symbols::{Allocation, Symbol}};
 
-mod lexer;
+pub mod lexer;
 pub mod compiler;
 pub mod error_handler;
 mod parser;
+mod listing;
+pub mod symbols;
+pub mod stdlib;
+pub mod sources;
+pub mod formatter;
+
+pub use self::parser::number_nodes::parse_number;
+
+/// Every address a program can jump to
+pub const ADDRESS_SPACE: usize = 0x10000;
+
+/// PUSH and POP use the first 256 bytes of RAM, since the stack pointer is 8 bits
+pub const STACK_SIZE: usize = 0x100;
+
+/// Settings that change what the sources compile to
+pub struct Settings {
+    /// Values every file can use, like constants
+    pub defines: HashMap<String, u16>,
+    /// Extra directories to look for includes in, after the directory of the including file
+    pub include_dirs: Vec<PathBuf>,
+    /// How many bytes the ROM holds. Anything over 64 KiB can't be addressed
+    pub rom_size: usize,
+    /// Remove redundant instructions after macros are expanded. Skipped with a warning if a jump goes to an address that isn't a label
+    pub optimize: bool,
+    /// The RAM addresses that variables are given, which should leave out the stack
+    pub ram: RangeInclusive<u16>,
+}
 
-pub fn compile() -> Result<Vec<u8>,()> {
-    if !Path::new("src").exists() {
-        error_handler::print_error("src does not exist");
-        return Err(());
-    }
-    env::set_current_dir("src").unwrap();
-    
-    let file = "main.bread";
-    let path = Path::new(&file);
-
-    if !path.exists() {
-        error_handler::print_error("main.bread does not exist");
-        return Err(());
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            defines: HashMap::new(),
+            include_dirs: Vec::new(),
+            rom_size: ADDRESS_SPACE,
+            optimize: false,
+            ram: STACK_SIZE as u16..=u16::MAX,
+        }
     }
+}
 
-    let file = path.file_name().unwrap();
+/// The result of compiling a program
+pub struct CompiledProgram {
+    pub bytes: Vec<u8>,
+    pub symbols: Vec<Symbol>,
+    /// Everything that was compiled, in order
+    pub listing: Vec<ListingEntry>,
+    /// Where each variable was put in RAM, in the order they were declared
+    pub variables: Vec<Allocation>,
+    pub warnings: Vec<CompilerError>,
+}
 
-    let contents = fs::read_to_string(file).unwrap();
-    
-    let tokens: Vec<Token>;
+impl CompiledProgram {
+    /// The contents of the .sym file
+    pub fn symbol_file(&self) -> String {
+        symbols::symbol_file(&self.symbols, &self.listing)
+    }
 
-    match scan_tokens(contents, String::from(file.to_str().unwrap())) {
-        Ok(t) => tokens = t,
-        Err(e) => {
-            e.print();
-            return Err(());
-        }
+    /// The contents of the .lst file
+    pub fn listing_file(&self) -> String {
+        listing::listing(&self.listing, &self.bytes)
     }
+}
 
-    let node: ProgramNode;
+/// Turns NAME=VALUE into a name and value. NAME on its own is 1
+pub fn parse_define(define: &str) -> Option<(String, u16)> {
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name.trim(), parse_number(value.trim()).and_then(Imm16::new)),
+        None => (define.trim(), Imm16::new(1)),
+    };
 
-    match parse(tokens, String::from(file.to_str().unwrap())) {
-        Ok(n) => node = n,
-        Err(e) => {
-            for error in e {
-                error.print();
-            }
-            return Err(());
-        }
+    match value {
+        Some(value) if !name.is_empty() => Some((name.to_string(), value.value())),
+        _ => None,
     }
+}
+
+/// Compiles the program whose main subroutine is in `entry`, reading every file from `sources`.
+/// Nothing is printed or written, errors and warnings are returned for the caller to report
+pub fn compile_sources(entry: &str, sources: &dyn SourceProvider, settings: &Settings) -> Result<CompiledProgram, Vec<CompilerError>> {
+    let file = entry.to_string();
+    let contents = match sources.read(Path::new(entry)) {
+        Some(contents) => contents,
+        None => return Err(vec![CompilerError::new(ErrorCode::NoSuchFile(file.clone()), &file, 0, true)]),
+    };
 
-    env::set_current_dir("..").unwrap();
+    let (tokens, lexer_errors) = scan_tokens(contents, file.clone());
+    let (node, warnings) = parse(tokens, lexer_errors, file, settings, sources)?;
 
     let mut compiler = Compiler::new();
     node.compile(&mut compiler);
-    
-    if !Path::new("bin").exists() {
-        DirBuilder::new().create("bin").unwrap();
-    }
-    let mut file = File::create("bin/program.crumbs").unwrap();
-    //for b in &compiler.bytes {
-    //    println!("{:08b}", b);
-    //}
-    file.write_all(&compiler.bytes).unwrap();
 
-    return Ok(compiler.bytes);
+    Ok(CompiledProgram {
+        bytes: compiler.bytes,
+        symbols: node.symbols,
+        listing: compiler.listing,
+        variables: node.allocations,
+        warnings
+    })
 }
 
 // enums
@@ -1,20 +1,46 @@
-use std::collections::HashMap;
+use super::{lexer::Token, Instruction, Register};
 
-use super::{parser::number_nodes::Imm16, Instruction, Register};
+/// Something that was compiled, in the order it was compiled
+pub enum ListingEntry {
+    Subroutine(String, u16),
+    Label(String, u16),
+    Macro(String, Token),
+    Instruction(SourceLine)
+}
+
+/// The bytes produced by a single line of source
+pub struct SourceLine {
+    pub address: u16,
+    pub size: u16,
+    pub file: String,
+    pub line: i32,
+    pub text: String
+}
 
 pub struct Compiler {
     pub bytes: Vec<u8>,
-    pub scope: HashMap<String, Imm16>
+    pub listing: Vec<ListingEntry>
 }
 
 impl Compiler {
     pub fn new() -> Compiler {
         Compiler {
             bytes: Vec::new(),
-            scope: HashMap::new()
+            listing: Vec::new()
         }
     }
 
+    /// Records that the bytes from address to the end were produced by token
+    pub fn add_source_line(&mut self, address: usize, token: &Token, text: String) {
+        self.listing.push(ListingEntry::Instruction(SourceLine {
+            address: address as u16,
+            size: (self.bytes.len() - address) as u16,
+            file: token.file.clone(),
+            line: token.line,
+            text
+        }));
+    }
+
     pub fn add_byte(&mut self, byte: u8) {
         self.bytes.push(byte)
     }
@@ -2,13 +2,46 @@ use core::fmt;
 
 use colored::Colorize;
 
-use super::lexer::{Token, TokenType};
+use super::{lexer::{Token, TokenType}, sources::SourceProvider, stdlib::read_source};
+
+/// Where in the source an error or note points to
+#[derive(Clone)]
+pub struct Location {
+    pub file: String,
+    /// 0 if the error is about the whole file
+    pub line: i32,
+    /// 0 if the column is unknown
+    pub column: i32,
+    pub length: u32
+}
+
+impl Location {
+    fn from_token(token: &Token) -> Location {
+        Location {
+            file: token.file.clone(),
+            line: token.line,
+            column: token.column,
+            length: token.len().max(1) as u32
+        }
+    }
+}
+
+pub struct Note {
+    pub message: String,
+    pub location: Option<Location>
+}
 
 pub struct CompilerError {
     file: String,
     line: i32,
+    /// 0 if the column is unknown
+    column: i32,
+    length: u32,
     code: ErrorCode,
-    critical: bool
+    critical: bool,
+    /// warnings are reported the same way as errors but don't stop the program from compiling
+    warning: bool,
+    notes: Vec<Note>
 }
 
 impl CompilerError {
@@ -16,8 +49,12 @@ impl CompilerError {
         CompilerError {
             file: file.clone(),
             line,
+            column: 0,
+            length: 0,
             code,
-            critical
+            critical,
+            warning: false,
+            notes: Vec::new()
         }
     }
 
@@ -26,12 +63,156 @@ impl CompilerError {
     }
 
     pub fn from_token(code: ErrorCode, token: &Token, critical: bool) -> CompilerError {
-        CompilerError::new(code, &token.file, token.line, critical)
+        CompilerError {
+            file: token.file.clone(),
+            line: token.line,
+            column: token.column,
+            length: token.len().max(1) as u32,
+            code,
+            critical,
+            warning: false,
+            notes: Vec::new()
+        }
+    }
+
+    pub fn warning(code: ErrorCode, token: &Token) -> CompilerError {
+        CompilerError {
+            warning: true,
+            ..CompilerError::from_token(code, token, false)
+        }
+    }
+
+    /// Adds a note pointing at another token, e.g. where a label was first defined
+    pub fn with_note(mut self, message: &str, token: &Token) -> CompilerError {
+        self.notes.push(Note {
+            message: message.to_string(),
+            location: Some(Location::from_token(token))
+        });
+        self
+    }
+
+    /// Adds a note that doesn't point anywhere in the source
+    pub fn with_help(mut self, message: &str) -> CompilerError {
+        self.notes.push(Note {
+            message: message.to_string(),
+            location: None
+        });
+        self
+    }
+
+    /// Returns true if this error was caused by text the lexer already reported
+    pub fn is_from_error_token(&self) -> bool {
+        matches!(self.code, ErrorCode::ExpectedButFound(_, TokenType::Error(_)))
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.warning
+    }
+
+    pub fn code(&self) -> &ErrorCode {
+        &self.code
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            length: self.length
+        }
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Prints the error, reading the source it points to from `sources`
+    pub fn print(&self, sources: &dyn SourceProvider) {
+        print!("{}", self.render(&|file| read_source(sources, file)));
+    }
+
+    /// Renders the error along with the source it points to. `source` returns the contents of a file
+    pub fn render(&self, source: &dyn Fn(&str) -> Option<String>) -> String {
+        let (header, severity) = if self.warning {
+            ("[Warning]".yellow().bold(), Severity::Warning)
+        } else {
+            ("[Error]".red().bold(), Severity::Error)
+        };
+        let mut text = format!("{}: {}\n", header, self.code);
+        let location = self.location();
+        // line numbers all get the same width so the bars line up
+        let width = self.notes.iter()
+            .filter_map(|n| n.location.as_ref())
+            .map(|l| l.line.to_string().len())
+            .fold(self.line.to_string().len(), usize::max);
+
+        text += &render_location(&location, source, severity, width);
+
+        for note in &self.notes {
+            match &note.location {
+                Some(location) => {
+                    text += &format!("{}: {}\n", "note".blue().bold(), note.message);
+                    text += &render_location(location, source, Severity::Note, width);
+                }
+                None => text += &format!("{} {} {}: {}\n", " ".repeat(width), "=".blue().bold(), "help".bold(), note.message),
+            }
+        }
+
+        text + "\n"
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Severity {
+    Error,
+    Warning,
+    Note
+}
+
+/// Renders the file name, the line of source and an underline beneath the part of the line being pointed to
+fn render_location(location: &Location, source: &dyn Fn(&str) -> Option<String>, severity: Severity, width: usize) -> String {
+    let gutter = " ".repeat(width);
+
+    if location.line <= 0 {
+        return format!("{}{} {}\n", gutter, "-->".blue().bold(), location.file);
     }
 
-    pub fn print(&self) {
-        println!("{}: {} at {}:{}", "[Error]".red().bold(), self.code, self.file, self.line);
+    let line = source(&location.file)
+        .and_then(|s| s.lines().nth(location.line as usize - 1).map(|l| l.to_string()));
+
+    let bar = "|".blue().bold();
+
+    let mut text = if location.column > 0 {
+        format!("{}{} {}:{}:{}\n", gutter, "-->".blue().bold(), location.file, location.line, location.column)
+    } else {
+        format!("{}{} {}:{}\n", gutter, "-->".blue().bold(), location.file, location.line)
+    };
+
+    let line = match line {
+        Some(line) => line,
+        None => return text,
+    };
+
+    text += &format!("{} {}\n", gutter, bar);
+    text += &format!("{:>width$} {} {}\n", location.line.to_string().blue().bold(), bar, line, width = width);
+
+    if location.column > 0 {
+        // keep tabs so the underline lines up with the source
+        let padding: String = line.chars()
+            .take(location.column as usize - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = (location.length as usize).min(line.chars().count().saturating_sub(location.column as usize - 1)).max(1);
+
+        let underline = match severity {
+            Severity::Error => "^".repeat(width).red().bold(),
+            Severity::Warning => "^".repeat(width).yellow().bold(),
+            Severity::Note => "-".repeat(width).blue().bold(),
+        };
+        text += &format!("{} {} {}{}\n", gutter, bar, padding, underline);
     }
+
+    text
 }
 
 pub fn has_critical(errors: &Vec<CompilerError>) -> bool {
@@ -48,45 +229,102 @@ pub fn print_error(msg: &str) {
     println!("{}: {}", "[Error]".red().bold(), msg);
 }
 
-pub fn print_warning(msg: &str) {
-    println!("{}: {}", "[Warning]".yellow().bold(), msg);
-}
-
 pub enum ErrorCode {
     // lexer errors
     InvalidNumber,
     UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedChar,
+    InvalidChar(String),
 
     // parser
     ExpectedButFound(String, TokenType),
-    NumberTooBig(i32),
+    NumberOutOfRange(i32, i32, i32),
+    DivisionByZero,
+    ExpressionOverflow,
+    CircularConstant(String),
+    NoSuchPlaceholder(String),
+    DuplicateSymbol(String),
+    UnmatchedConditional(TokenType),
+    MissingEndIf,
     NoSuchMacro(String),
-    MacroCallsMacro,
+    WrongArgumentCount(String, usize, usize),
+    RecursiveMacro(Vec<String>),
+    MacroTooDeep(usize),
+    MacroTooLarge(usize),
+    RomOverflow(String, usize, usize),
+    RamOverflow(String, u16),
 
     // files
     NoSuchFile(String),
+    FormattingChangedCode,
     NoMainSubroutine,
+
+    // warnings
+    DeprecatedInstruction(String, String),
+    NoReturn(String),
+    EmptySubroutine(String),
+    NotOptimized,
 }
 
 impl fmt::Display for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
             Self::InvalidNumber => write!(f,
-                "Invalid Number: Only positive decimal, hexadecimal, and binary numbers are allowed."),
+                "Invalid Number: Numbers can be decimal, hexadecimal (0x), octal (0o), binary (0b) or a character such as 'A'."),
             
             Self::UnexpectedChar(c) => write!(f, "Unexpected Char \'{}\'", c),
 
+            Self::UnterminatedString => write!(f, "Missing closing \" before the end of the line"),
+
+            Self::UnterminatedChar => write!(f, "Missing closing \' before the end of the line"),
+
+            Self::InvalidChar(text) => write!(f, "\'{}\' is not a character. Use one character below 256 or an escape such as \\n or \\x41", text),
+
             Self::ExpectedButFound(expected, found) => 
                 write!(f, "Expected {} but found {:?}", expected, found),
             
-            Self::NumberTooBig(num) => write!(f, "Number {} is too large", num),
+            Self::NumberOutOfRange(num, min, max) => write!(f, "Number {} does not fit, it has to be between {} and {}", num, min, max),
+
+            Self::DivisionByZero => write!(f, "Division by zero"),
+
+            Self::ExpressionOverflow => write!(f, "Expression overflowed while it was being calculated"),
+
+            Self::CircularConstant(name) => write!(f, "Constant {} depends on itself", name),
+
+            Self::NoSuchPlaceholder(name) => write!(f, "{} is not defined", name),
+
+            Self::DuplicateSymbol(name) => write!(f, "{} is defined more than once", name),
+
+            Self::UnmatchedConditional(directive) => write!(f, "{} without a matching @if", directive),
+
+            Self::MissingEndIf => write!(f, "@if is missing a matching @endif"),
 
             Self::NoSuchMacro(name) => write!(f, "A macro named {} does not exist", name),
 
-            Self::MacroCallsMacro => write!(f, "Macros cannot call other macros"),
+            Self::WrongArgumentCount(name, expected, found) =>
+                write!(f, "Macro {} takes {} arguments but {} were given", name, expected, found),
+
+            Self::RecursiveMacro(chain) => write!(f, "Macro {} calls itself: {}", chain[0], chain.join(" -> ")),
+
+            Self::MacroTooDeep(depth) => write!(f, "Macros cannot be nested more than {} deep", depth),
+
+            Self::MacroTooLarge(limit) => write!(f, "Macros expand to more than {} instructions", limit),
+
+            Self::RomOverflow(name, end, size) =>
+                write!(f, "Subroutine {} ends at byte {} but the ROM only holds {} bytes", name, end, size),
+
+            Self::RamOverflow(name, size) => write!(f, "Variable {} needs {} bytes but there is no room left in RAM", name, size),
 
             Self::NoSuchFile(filename) => write!(f, "File {} does not exist", filename),
+            Self::FormattingChangedCode => write!(f, "Formatting would change the code of this file, so it was left alone. This is a bug in the formatter"),
             Self::NoMainSubroutine => write!(f, "A \"main\" subroutine is required"),
+
+            Self::DeprecatedInstruction(old, new) =>
+                write!(f, "Use of the {} instruction is deprecated. Use {} instead.", old, new),
+            Self::NoReturn(name) => write!(f, "Subroutine {} does not end in HLT, JMP or RET", name),
+            Self::EmptySubroutine(name) => write!(f, "Subroutine {} does not contain any instructions", name),
+            Self::NotOptimized => write!(f, "The program was not optimized because this jumps to an address that isn't a label, which optimizing could move"),
         }
     }
 }
\ No newline at end of file
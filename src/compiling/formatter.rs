@@ -0,0 +1,175 @@
+use super::{error_handler::{CompilerError, ErrorCode}, lexer::{scan_tokens, Token, TokenType}, parse_number};
+
+/// Trailing comments start at this column, unless the code is already past it
+const COMMENT_COLUMN: usize = 24;
+/// How wide a tab is when lining up comments
+const TAB_WIDTH: usize = 4;
+
+enum Line {
+    Blank,
+    Comment(String),
+    Code {
+        indented: bool,
+        code: String,
+        comment: Option<String>,
+    },
+}
+
+/// Formats a file the same way every time. Only whitespace and the casing of number prefixes and hex digits change,
+/// so the file always compiles to the same bytes. Files with lexer errors are returned as errors instead,
+/// as are files the formatter would change the code of, which would be a bug in the formatter
+pub fn format(text: &str, file: &str) -> Result<String, Vec<CompilerError>> {
+    let (tokens, errors) = scan_tokens(text.to_string(), file.to_string());
+    let errors: Vec<CompilerError> = errors.into_iter().filter(|e| !e.is_warning()).collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut lines: Vec<Line> = Vec::new();
+    let mut start = 0;
+
+    for (i, source) in text.split('\n').enumerate() {
+        let length = source.chars().count();
+        let line: Vec<&Token> = tokens.iter()
+            .filter(|t| t.line == i as i32 + 1 && !matches!(t.token_type, TokenType::NewLine | TokenType::EndOfFile))
+            .collect();
+
+        lines.push(read_line(&line, &chars[start..start + length], start));
+        start += length + 1;
+    }
+
+    let mut out = String::new();
+    let mut blank = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        match line {
+            Line::Blank => blank = true,
+            Line::Comment(comment) => {
+                push_blank(&mut out, &mut blank);
+
+                if in_block(&lines, i) {
+                    out.push('\t');
+                }
+                out += comment;
+                out.push('\n');
+            }
+            Line::Code { indented, code, comment } => {
+                push_blank(&mut out, &mut blank);
+
+                let mut line = if *indented { format!("\t{}", code) } else { code.clone() };
+
+                if let Some(comment) = comment {
+                    let width = line.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum::<usize>();
+                    line += &" ".repeat(COMMENT_COLUMN.saturating_sub(width).max(1));
+                    line += comment;
+                }
+
+                out += &line;
+                out.push('\n');
+            }
+        }
+    }
+
+    let (formatted, _) = scan_tokens(out.clone(), file.to_string());
+    if !same_code(&tokens, &formatted) {
+        return Err(vec![CompilerError::new(ErrorCode::FormattingChangedCode, &file.to_string(), 0, true)]);
+    }
+
+    Ok(out)
+}
+
+/// Writes the blank line before this one, if there was one. Runs of blank lines become one, and the start of the file has none
+fn push_blank(out: &mut String, blank: &mut bool) {
+    if *blank && !out.is_empty() {
+        out.push('\n');
+    }
+    *blank = false;
+}
+
+/// Comment lines between the lines of a subroutine or macro are indented along with them
+fn in_block(lines: &[Line], i: usize) -> bool {
+    let code = |line: &Line| match line {
+        Line::Code { indented, code, .. } => Some((*indented, code.ends_with(':'))),
+        _ => None,
+    };
+
+    let previous = lines[..i].iter().rev().find_map(code);
+    let next = lines[i + 1..].iter().find_map(code);
+
+    matches!(previous, Some((true, _)) | Some((false, true))) && matches!(next, Some((true, _)))
+}
+
+/// Turns a line into its code and comment. `chars` is the text of the line, which starts at `start` in the file
+fn read_line(line: &[&Token], chars: &[char], start: usize) -> Line {
+    let indented = matches!(line.first().map(|t| &t.token_type), Some(TokenType::Indent(_)));
+    let code: Vec<&Token> = line.iter().copied().filter(|t| !matches!(t.token_type, TokenType::Indent(_))).collect();
+
+    // anything after the last token that isn't whitespace is a comment
+    let rest: String = match line.last() {
+        Some(token) => chars[token.end - start..].iter().collect(),
+        None => chars.iter().collect(),
+    };
+    let comment = rest.find(';').map(|i| rest[i..].trim_end().to_string());
+
+    if code.is_empty() {
+        return match comment {
+            Some(comment) => Line::Comment(comment),
+            None => Line::Blank,
+        };
+    }
+
+    let mut text = String::new();
+    for (i, token) in code.iter().enumerate() {
+        // tokens keep any space between them, but only one
+        if i > 0 && code[i - 1].end < token.start {
+            text.push(' ');
+        }
+
+        match &token.token_type {
+            TokenType::Number(number) => text += &format_number(number),
+            _ => text.extend(&chars[token.start - start..token.end - start]),
+        }
+    }
+
+    Line::Code { indented, code: text, comment }
+}
+
+/// Lowercase prefixes and uppercase hex digits, like 0x00FF and 0b0101. Characters are left alone
+fn format_number(number: &str) -> String {
+    if number.starts_with('\'') {
+        return number.to_string();
+    }
+
+    let lower = number.to_lowercase();
+
+    if let Some(digits) = lower.strip_prefix("0x") {
+        format!("0x{}", digits.to_uppercase())
+    } else {
+        lower
+    }
+}
+
+/// Checks that two token lists would compile the same way.
+/// Indents only matter for whether a line has one, and numbers only for their value
+fn same_code(a: &[Token], b: &[Token]) -> bool {
+    fn lines(tokens: &[Token]) -> Vec<(bool, Vec<&Token>)> {
+        tokens.split(|t| matches!(t.token_type, TokenType::NewLine | TokenType::EndOfFile))
+            .map(|line| {
+                let indented = matches!(line.first().map(|t| &t.token_type), Some(TokenType::Indent(_)));
+                (indented, line.iter().filter(|t| !matches!(t.token_type, TokenType::Indent(_))).collect::<Vec<_>>())
+            })
+            .filter(|(_, line)| !line.is_empty())
+            .collect()
+    }
+
+    let same_token = |a: &&Token, b: &&Token| match (&a.token_type, &b.token_type) {
+        (TokenType::Number(a), TokenType::Number(b)) => parse_number(a) == parse_number(b),
+        (a, b) => a == b,
+    };
+
+    let (a, b) = (lines(a), lines(b));
+    a.len() == b.len() && a.iter().zip(&b).all(|((a_indented, a), (b_indented, b))| {
+        a_indented == b_indented && a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_token(a, b))
+    })
+}
@@ -1,4 +1,6 @@
-use super::{error_handler::{self, CompilerError, ErrorCode}, Instruction, Register};
+use core::fmt;
+
+use super::{error_handler::{CompilerError, ErrorCode}, parse_number, Instruction, Register};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenType {
@@ -12,24 +14,106 @@ pub enum TokenType {
     OpenParenthesis,
     CloseParenthesis,
 
+    // operators
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
     // keywords
     Macro,
     Include,
+    Variable,
+    Reserve,
+    If,
+    IfDef,
+    IfNDef,
+    Else,
+    EndIf,
     Def,
     Constant,
+    Call,
+    Return,
     Register(Register),
 
     Instruction(Instruction),
 
     Indent(String),
     EndOfFile,
-    NewLine
+    NewLine,
+
+    /// text that could not be scanned, the error has already been reported
+    Error(String)
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenType::Identifier(s) | TokenType::Number(s) | TokenType::Indent(s) | TokenType::Error(s) => write!(f, "{}", s),
+            TokenType::Colon => write!(f, ":"),
+            TokenType::OpenParenthesis => write!(f, "("),
+            TokenType::CloseParenthesis => write!(f, ")"),
+            TokenType::Plus => write!(f, "+"),
+            TokenType::Minus => write!(f, "-"),
+            TokenType::Star => write!(f, "*"),
+            TokenType::Slash => write!(f, "/"),
+            TokenType::Percent => write!(f, "%"),
+            TokenType::Ampersand => write!(f, "&"),
+            TokenType::Pipe => write!(f, "|"),
+            TokenType::Caret => write!(f, "^"),
+            TokenType::ShiftLeft => write!(f, "<<"),
+            TokenType::ShiftRight => write!(f, ">>"),
+            TokenType::Equal => write!(f, "=="),
+            TokenType::NotEqual => write!(f, "!="),
+            TokenType::Less => write!(f, "<"),
+            TokenType::LessEqual => write!(f, "<="),
+            TokenType::Greater => write!(f, ">"),
+            TokenType::GreaterEqual => write!(f, ">="),
+            TokenType::Macro => write!(f, "@macro"),
+            TokenType::Include => write!(f, "@include"),
+            TokenType::Variable => write!(f, "@var"),
+            TokenType::Reserve => write!(f, "@reserve"),
+            TokenType::If => write!(f, "@if"),
+            TokenType::IfDef => write!(f, "@ifdef"),
+            TokenType::IfNDef => write!(f, "@ifndef"),
+            TokenType::Else => write!(f, "@else"),
+            TokenType::EndIf => write!(f, "@endif"),
+            TokenType::Def => write!(f, "DEF"),
+            TokenType::Constant => write!(f, "const"),
+            TokenType::Call => write!(f, "CALL"),
+            TokenType::Return => write!(f, "RET"),
+            TokenType::Register(reg) => write!(f, "{:?}", reg),
+            TokenType::Instruction(instruction) => write!(f, "{:?}", instruction),
+            TokenType::EndOfFile => Ok(()),
+            TokenType::NewLine => writeln!(f),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub line: i32,
+    /// column of the first character starting at 1, or 0 if the token did not come from a file
+    pub column: i32,
+    /// offset of the first character in the file
+    pub start: usize,
+    /// offset just past the last character in the file
+    pub end: usize,
+    /// true if a space or tab comes right before the token
+    pub spaced: bool,
     pub file: String
 }
 
@@ -38,16 +122,39 @@ impl Token {
         Token {
             token_type,
             line,
+            column: 0,
+            start: 0,
+            end: 0,
+            spaced: false,
             file
         }
     }
+
+    /// Creates a token in the same place as another one
+    pub fn at(token_type: TokenType, token: &Token) -> Token {
+        Token {
+            token_type,
+            ..token.clone()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 struct Tokenizer {
     start: usize,
     current: usize,
     line: i32,
+    /// offset of the first character on the current line
+    line_start: usize,
     tokens: Vec<Token>,
+    warnings: Vec<CompilerError>,
     chars: Vec<char>,
     filename: String
 }
@@ -59,7 +166,9 @@ impl Tokenizer {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
             tokens: Vec::new(),
+            warnings: Vec::new(),
             chars: text.chars().collect(),
             filename
         }
@@ -87,7 +196,21 @@ impl Tokenizer {
     }
 
     pub fn add_token(&mut self, token: TokenType) {
-        self.tokens.push(Token::new(token, self.line, self.filename.clone()))
+        let token = self.make_token(token);
+        self.tokens.push(token)
+    }
+
+    /// Creates a token covering everything from start to current
+    pub fn make_token(&self, token: TokenType) -> Token {
+        Token {
+            token_type: token,
+            line: self.line,
+            column: (self.start - self.line_start + 1) as i32,
+            start: self.start,
+            end: self.current,
+            spaced: self.start > 0 && matches!(self.chars[self.start - 1], ' ' | '\t'),
+            file: self.filename.clone()
+        }
     }
 
     pub fn get_string(&self) -> String {
@@ -100,20 +223,38 @@ impl Tokenizer {
     }
 }
 
-pub fn scan_tokens(text: String, filename: String) -> Result<Vec<Token>, CompilerError> {
+/// Scans the whole file, returning every token along with every error and warning found.
+/// Text that can't be scanned becomes an error token so the parser can keep going.
+pub fn scan_tokens(text: String, filename: String) -> (Vec<Token>, Vec<CompilerError>) {
     let mut tokenizer = Tokenizer::new(text, filename);
+    let mut errors: Vec<CompilerError> = Vec::new();
 
     while !tokenizer.is_at_end() {
         tokenizer.start = tokenizer.current;
-        scan_token(&mut tokenizer)?;
+        if let Err(code) = scan_token(&mut tokenizer) {
+            let token = recover(&mut tokenizer);
+            errors.push(CompilerError::from_token(code, &token, true));
+        }
     }
 
-    tokenizer.tokens.push(Token::new(TokenType::EndOfFile, tokenizer.line, tokenizer.filename.clone()));
+    tokenizer.start = tokenizer.current;
+    tokenizer.add_token(TokenType::EndOfFile);
 
-    Ok(tokenizer.tokens)
+    errors.append(&mut tokenizer.warnings);
+    (tokenizer.tokens, errors)
 }
 
-fn scan_token(tokenizer: &mut Tokenizer) -> Result<(), CompilerError> {
+/// Skips to the next whitespace or comment and turns everything skipped into an error token
+fn recover(tokenizer: &mut Tokenizer) -> Token {
+    while !tokenizer.is_at_end() && !tokenizer.peek().is_whitespace() && tokenizer.peek() != ';' {
+        tokenizer.advance();
+    }
+
+    tokenizer.add_token(TokenType::Error(tokenizer.get_string()));
+    tokenizer.tokens.last().unwrap().clone()
+}
+
+fn scan_token(tokenizer: &mut Tokenizer) -> Result<(), ErrorCode> {
     match tokenizer.advance() {
 
         // 1 line chars
@@ -122,6 +263,54 @@ fn scan_token(tokenizer: &mut Tokenizer) -> Result<(), CompilerError> {
         '(' => tokenizer.add_token(TokenType::OpenParenthesis),
         ')' => tokenizer.add_token(TokenType::CloseParenthesis),
 
+        // operators
+        '+' => tokenizer.add_token(TokenType::Plus),
+        '-' => tokenizer.add_token(TokenType::Minus),
+        '*' => tokenizer.add_token(TokenType::Star),
+        '/' => tokenizer.add_token(TokenType::Slash),
+        '%' => tokenizer.add_token(TokenType::Percent),
+        '&' => tokenizer.add_token(TokenType::Ampersand),
+        '|' => tokenizer.add_token(TokenType::Pipe),
+        '^' => tokenizer.add_token(TokenType::Caret),
+        '<' => {
+            match tokenizer.peek() {
+                '<' => {
+                    tokenizer.advance();
+                    tokenizer.add_token(TokenType::ShiftLeft);
+                }
+                '=' => {
+                    tokenizer.advance();
+                    tokenizer.add_token(TokenType::LessEqual);
+                }
+                _ => tokenizer.add_token(TokenType::Less)
+            }
+        }
+        '>' => {
+            match tokenizer.peek() {
+                '>' => {
+                    tokenizer.advance();
+                    tokenizer.add_token(TokenType::ShiftRight);
+                }
+                '=' => {
+                    tokenizer.advance();
+                    tokenizer.add_token(TokenType::GreaterEqual);
+                }
+                _ => tokenizer.add_token(TokenType::Greater)
+            }
+        }
+        '=' | '!' => {
+            if tokenizer.peek() != '=' {
+                return Err(ErrorCode::UnexpectedChar(tokenizer.char()));
+            }
+            tokenizer.advance();
+
+            if tokenizer.char() == '=' {
+                tokenizer.add_token(TokenType::Equal);
+            } else {
+                tokenizer.add_token(TokenType::NotEqual);
+            }
+        }
+
         // comments
         ';' => {
             while tokenizer.peek() != '\n' && !tokenizer.is_at_end() {
@@ -141,19 +330,22 @@ fn scan_token(tokenizer: &mut Tokenizer) -> Result<(), CompilerError> {
             tokenizer.add_token(TokenType::Indent(tokenizer.get_string()))
         }
 
+        '\'' => return character(tokenizer),
+
         '\r' => (), // ignore
         '\n' => {
-            tokenizer.line += 1;
             tokenizer.add_token(TokenType::NewLine);
+            tokenizer.line += 1;
+            tokenizer.line_start = tokenizer.current;
         }
 
         _ => {
             if tokenizer.char().is_digit(10) {
                 return number(tokenizer)
             } else if is_alphabetic(tokenizer.char()) {
-                identifier(tokenizer);
+                return identifier(tokenizer);
             } else {
-                return Err(CompilerError::new(ErrorCode::UnexpectedChar(tokenizer.char()), &tokenizer.filename, tokenizer.line, true));
+                return Err(ErrorCode::UnexpectedChar(tokenizer.char()));
             }
         }
     };
@@ -161,42 +353,59 @@ fn scan_token(tokenizer: &mut Tokenizer) -> Result<(), CompilerError> {
     return Ok(());
 }
 
-fn number(tokenizer: &mut Tokenizer) -> Result<(), CompilerError> {
-    if tokenizer.char() == '0' {
-        match tokenizer.peek() {
-            // hex number
-            'x' | 'X' => {
-                tokenizer.advance();
-                while tokenizer.peek().is_digit(16) {
-                    tokenizer.advance();
-                }
-            }
+fn number(tokenizer: &mut Tokenizer) -> Result<(), ErrorCode> {
+    let radix = match (tokenizer.char(), tokenizer.peek()) {
+        ('0', 'x' | 'X') => 16,
+        ('0', 'o' | 'O') => 8,
+        ('0', 'b' | 'B') => 2,
+        _ => 10,
+    };
 
-            // binary number
-            'b' | 'B' => {
-                tokenizer.advance();
-                while tokenizer.peek().is_digit(2) || tokenizer.peek() == '_' {
-                    tokenizer.advance();
-                }
-            }
-            _ => {
-                while tokenizer.peek().is_digit(10) || tokenizer.peek() == '_' {
-                    tokenizer.advance();
-                }
-            }
+    // skip the prefix
+    if radix != 10 {
+        tokenizer.advance();
+    }
+
+    while tokenizer.peek().is_digit(radix) || tokenizer.peek() == '_' {
+        tokenizer.advance();
+    }
+
+    // numbers can't run into letters, and need digits after the prefix
+    let number = tokenizer.get_string();
+    if is_alphanumeric(tokenizer.peek()) || parse_number(&number).is_none() {
+        return Err(ErrorCode::InvalidNumber);
+    }
+
+    tokenizer.add_token(TokenType::Number(number));
+
+    return Ok(())
+}
+
+/// A character in single quotes, such as 'A' or '\n'. It becomes a number token so it can be used anywhere a number can
+fn character(tokenizer: &mut Tokenizer) -> Result<(), ErrorCode> {
+    while tokenizer.peek() != '\'' {
+        if tokenizer.is_at_end() || matches!(tokenizer.peek(), '\n' | '\r') {
+            return Err(ErrorCode::UnterminatedChar);
         }
-    } else {
-        while tokenizer.peek().is_digit(10) || tokenizer.peek() == '_' {
+
+        // an escaped quote doesn't end the character
+        if tokenizer.advance() == '\\' && !tokenizer.is_at_end() && !matches!(tokenizer.peek(), '\n' | '\r') {
             tokenizer.advance();
         }
     }
+    tokenizer.advance();
 
-    tokenizer.add_token(TokenType::Number(tokenizer.get_string()));
+    let literal = tokenizer.get_string();
+    if parse_number(&literal).is_none() {
+        return Err(ErrorCode::InvalidChar(literal[1..literal.len() - 1].to_string()));
+    }
 
-    return Ok(())
+    tokenizer.add_token(TokenType::Number(literal));
+
+    Ok(())
 }
 
-fn identifier(tokenizer: &mut Tokenizer) {
+fn identifier(tokenizer: &mut Tokenizer) -> Result<(), ErrorCode> {
     while is_alphanumeric(tokenizer.peek()) {
         tokenizer.advance();
     }
@@ -214,8 +423,9 @@ fn identifier(tokenizer: &mut Tokenizer) {
         "JZ" => tokenizer.add_token(TokenType::Instruction(Instruction::JZ)),
         
         "JO" => {
-            error_handler::print_warning(&format!("Use of the JO instruction is deprecated at {}:{}. Use JC instead.", tokenizer.filename, tokenizer.line));
-            tokenizer.add_token(TokenType::Instruction(Instruction::JC))
+            tokenizer.add_token(TokenType::Instruction(Instruction::JC));
+            let warning = CompilerError::warning(ErrorCode::DeprecatedInstruction("JO".to_string(), "JC".to_string()), tokenizer.tokens.last().unwrap());
+            tokenizer.warnings.push(warning);
         },
         "JC" => tokenizer.add_token(TokenType::Instruction(Instruction::JC)),
 
@@ -223,10 +433,24 @@ fn identifier(tokenizer: &mut Tokenizer) {
         "SUB" => tokenizer.add_token(TokenType::Instruction(Instruction::SUB)),
         "OUT" => tokenizer.add_token(TokenType::Instruction(Instruction::OUT)),
         "HLT" => tokenizer.add_token(TokenType::Instruction(Instruction::HLT)),
+
+        // pseudo instructions
+        "CALL" => tokenizer.add_token(TokenType::Call),
+        "RET" => tokenizer.add_token(TokenType::Return),
         
         // keywords
         "@macro" => tokenizer.add_token(TokenType::Macro),
-        "@include" => tokenizer.add_token(TokenType::Include),
+        "@include" => {
+            tokenizer.add_token(TokenType::Include);
+            return include_path(tokenizer);
+        },
+        "@var" => tokenizer.add_token(TokenType::Variable),
+        "@reserve" => tokenizer.add_token(TokenType::Reserve),
+        "@if" => tokenizer.add_token(TokenType::If),
+        "@ifdef" => tokenizer.add_token(TokenType::IfDef),
+        "@ifndef" => tokenizer.add_token(TokenType::IfNDef),
+        "@else" => tokenizer.add_token(TokenType::Else),
+        "@endif" => tokenizer.add_token(TokenType::EndIf),
         "DEF" => tokenizer.add_token(TokenType::Def),
         "const" => tokenizer.add_token(TokenType::Constant),
 
@@ -237,6 +461,49 @@ fn identifier(tokenizer: &mut Tokenizer) {
 
         _ => tokenizer.add_token(TokenType::Identifier(tokenizer.get_string()))
     }
+
+    Ok(())
+}
+
+/// The rest of an include line is a path, which may contain characters that are not allowed in identifiers.
+/// Paths can be quoted to include spaces or semicolons
+fn include_path(tokenizer: &mut Tokenizer) -> Result<(), ErrorCode> {
+    while tokenizer.peek() == ' ' || tokenizer.peek() == '\t' {
+        tokenizer.advance();
+    }
+
+    tokenizer.start = tokenizer.current;
+
+    if tokenizer.peek() == '"' {
+        tokenizer.advance();
+        while tokenizer.peek() != '"' {
+            if tokenizer.is_at_end() || matches!(tokenizer.peek(), '\n' | '\r') {
+                return Err(ErrorCode::UnterminatedString);
+            }
+            tokenizer.advance();
+        }
+        tokenizer.advance();
+
+        let path = tokenizer.get_string();
+        tokenizer.add_token(TokenType::Identifier(path[1..path.len() - 1].to_string()));
+        return Ok(());
+    }
+
+    while !tokenizer.is_at_end() && !matches!(tokenizer.peek(), '\n' | '\r' | ';') {
+        tokenizer.advance();
+    }
+
+    // leave trailing whitespace out of the path
+    while tokenizer.current > tokenizer.start && tokenizer.char().is_whitespace() {
+        tokenizer.current -= 1;
+    }
+
+    let path = tokenizer.get_string();
+    if !path.is_empty() {
+        tokenizer.add_token(TokenType::Identifier(path));
+    }
+
+    Ok(())
 }
 
 fn is_alphanumeric(char: char) -> bool {
@@ -245,5 +512,5 @@ fn is_alphanumeric(char: char) -> bool {
 
 fn is_alphabetic(char: char) -> bool {
     (char >= 'a' && char <= 'z') || (char >= 'A' && char <= 'Z') || 
-        char == '_' || char == '@' || char == '.' || char == '\\' || char == '/' || char == '\''
+        char == '_' || char == '@' || char == '.'
 }
\ No newline at end of file
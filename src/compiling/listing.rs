@@ -0,0 +1,45 @@
+use super::compiler::{ListingEntry, SourceLine};
+
+/// Formats a compiled program as a listing with the address, bytes and source of every instruction
+pub fn listing(entries: &[ListingEntry], bytes: &[u8]) -> String {
+    let mut out = format!("{:<6}{:<10}{:<28}{:<24}{}\n", "ADDR", "HEX", "BINARY", "SOURCE", "INSTRUCTION");
+
+    for entry in entries {
+        match entry {
+            ListingEntry::Subroutine(name, address) => {
+                out += &format!("\n{:04X}  {}:\n", address, name);
+            }
+            ListingEntry::Label(name, address) => {
+                out += &format!("{:04X}  DEF {}\n", address, name);
+            }
+            ListingEntry::Macro(text, token) => {
+                out += &format!("{:<64}; macro {} ({}:{})\n", "", text, token.file, token.line);
+            }
+            ListingEntry::Instruction(line) => out += &format_line(line, bytes),
+        }
+    }
+
+    out
+}
+
+fn format_line(line: &SourceLine, bytes: &[u8]) -> String {
+    let start = line.address as usize;
+    let bytes = &bytes[start..start + line.size as usize];
+    let source = format!("{}:{}", line.file, line.line);
+    let mut out = String::new();
+
+    // pseudo instructions are longer than a row, so they carry on below
+    for (i, row) in bytes.chunks(3).enumerate() {
+        let hex: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+        let binary: Vec<String> = row.iter().map(|b| format!("{:08b}", b)).collect();
+        let address = line.address as usize + i * 3;
+
+        if i == 0 {
+            out += &format!("{:04X}  {:<10}{:<28}{:<24}{}\n", address, hex.join(" "), binary.join(" "), source, line.text);
+        } else {
+            out += &format!("{:04X}  {:<10}{}\n", address, hex.join(" "), binary.join(" "));
+        }
+    }
+
+    out
+}
@@ -6,32 +6,110 @@ mod register_node;
 mod macros;
 mod reg_imm_node;
 mod placeholder_node;
+mod expression_node;
+mod preprocessor;
+mod optimizer;
+mod variable_node;
+
+use std::{collections::{HashMap, HashSet}, ops::RangeInclusive, path::{Path, PathBuf}};
+use self::{number_nodes::Imm16, program_node::ProgramNode};
+
+use super::{Settings, ADDRESS_SPACE, STACK_SIZE, compiler::Compiler, error_handler::{CompilerError, ErrorCode}, lexer::{scan_tokens, Token, TokenType}, sources::{find_include, Include, SourceProvider}};
+
+/// Parses the tokens of the main file. Lexer errors found while scanning are reported along with any errors found while parsing.
+/// Warnings come first in the list of errors, or along with the program if there were no errors
+pub fn parse(tokens: Vec<Token>, lexer_errors: Vec<CompilerError>, file: String, settings: &Settings, sources: &dyn SourceProvider) -> Result<(ProgramNode, Vec<CompilerError>), Vec<CompilerError>> {
+    let mut parser = Parser::new(Vec::new(), file, sources);
+    parser.lexer_errors = lexer_errors;
+    parser.include_dirs = settings.include_dirs.clone();
+    parser.rom_size = settings.rom_size.min(ADDRESS_SPACE);
+    parser.optimize = settings.optimize;
+    parser.ram = settings.ram.clone();
+
+    let defines: HashMap<String, Imm16> = settings.defines.iter()
+        .map(|(name, value)| (name.clone(), Imm16::from(*value)))
+        .collect();
+    let mut known = defines.clone();
+    parser.defines = defines;
+
+    let result = match preprocessor::preprocess(&mut parser, tokens, &mut known) {
+        Ok(tokens) => {
+            parser.tokens = tokens;
+            ProgramNode::populate(&mut parser)
+        }
+        Err(e) => Err(e),
+    };
+
+    let (mut warnings, mut errors): (Vec<CompilerError>, Vec<CompilerError>) = std::mem::take(&mut parser.lexer_errors)
+        .into_iter()
+        .filter(|e| {
+            let location = e.location();
+            !parser.skipped.contains(&(location.file, location.line))
+        })
+        .partition(|e| e.is_warning());
+    warnings.append(&mut parser.warnings);
+
+    match result {
+        Ok(node) if errors.is_empty() => return Ok((node, warnings)),
+        Ok(_) => (),
+        Err(e) => {
+            // errors about error tokens were already reported by the lexer
+            errors.extend(e.into_iter().filter(|e| !e.is_from_error_token()));
+        }
+    }
 
-use std::{fs, path::Path};
-use self::program_node::ProgramNode;
-
-use super::{compiler::Compiler, error_handler::{CompilerError, ErrorCode}, lexer::{scan_tokens, Token, TokenType}};
-
-pub fn parse(tokens: Vec<Token>, file: String) -> Result<ProgramNode, Vec<CompilerError>> {
-    let mut parser = Parser::new(tokens, file);
-    ProgramNode::populate(&mut parser)
+    warnings.append(&mut errors);
+    Err(warnings)
 }
 
-pub struct Parser {
+pub struct Parser<'a> {
     tokens: Vec<Token>,
     current: usize,
-    files: Vec<String>
+    /// name of the file being compiled, which has to contain the main subroutine
+    entry: String,
+    /// canonical paths of every file read so far, so each is only included once
+    files: Vec<PathBuf>,
+    /// directories searched for includes after the directory of the including file
+    include_dirs: Vec<PathBuf>,
+    /// values given on the command line
+    defines: HashMap<String, Imm16>,
+    /// how many bytes the program can take up
+    rom_size: usize,
+    /// run the peephole optimizer once macros are expanded
+    optimize: bool,
+    /// where variables can be put
+    ram: RangeInclusive<u16>,
+    lexer_errors: Vec<CompilerError>,
+    /// the file and line of everything left out by @if, so errors the lexer found in them can be ignored
+    skipped: HashSet<(String, i32)>,
+    warnings: Vec<CompilerError>,
+    sources: &'a dyn SourceProvider
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>, file: String) -> Parser {
+impl Parser<'_> {
+    fn new(tokens: Vec<Token>, file: String, sources: &dyn SourceProvider) -> Parser<'_> {
         Parser {
             tokens,
             current: 0,
-            files: vec![file]
+            files: vec![sources.canonical(Path::new(&file))],
+            entry: file,
+            include_dirs: Vec::new(),
+            defines: HashMap::new(),
+            rom_size: ADDRESS_SPACE,
+            optimize: false,
+            ram: STACK_SIZE as u16..=u16::MAX,
+            lexer_errors: Vec::new(),
+            skipped: HashSet::new(),
+            warnings: Vec::new(),
+            sources
         }
     }
 
+    /// Parses tokens that have already been scanned, such as a macro body or a condition
+    fn from_tokens(tokens: Vec<Token>, file: String) -> Parser<'static> {
+        Parser::new(tokens, file, &NoSources)
+    }
+
     /// Returns the current token
     fn current(&self) -> &Token {
         if self.current == 0 {
@@ -50,6 +128,15 @@ impl Parser {
         }
     }
 
+    /// Returns the token after the next token
+    fn peek_next(&self) -> &Token {
+        if self.current + 1 >= self.tokens.len() {
+            self.tokens.last().unwrap()
+        } else {
+            &self.tokens[self.current + 1]
+        }
+    }
+
     /// Returns the current token and advances to the next token
     fn advance(&mut self) -> &Token {
         if self.is_at_end() {
@@ -60,12 +147,6 @@ impl Parser {
         self.tokens.get(self.current - 1).expect("Out of bounds")
     }
 
-    fn insert(&mut self, tokens: Vec<Token>) {
-        for i in 0..tokens.len() {
-            self.tokens.insert(self.current + i, tokens[i].clone());
-        }
-    }
-
     /// Returns true if at there are no more tokens
     fn is_at_end(&self) -> bool {
         self.current >= self.tokens.len()
@@ -78,37 +159,55 @@ impl Parser {
         }
     }
 
-    // files
-    fn add_file(&mut self, file: &String) -> Result<(), CompilerError> {
-        if !self.files.contains(file) {
-            let path = Path::new(&file);
-
-            if !path.exists() {
-                return Err(CompilerError::new(
-                    ErrorCode::NoSuchFile(file.clone()), &self.current().file, self.current().line, true)
-                );
+    /// Gives every name starting with . in the lines of a subroutine the name of the subroutine,
+    /// so `.loop` in `main` becomes `main.loop`. The lines start at the current token and end at the first one that isn't indented
+    fn qualify_local_labels(&mut self, subroutine: &str) {
+        let mut line_start = true;
+
+        for token in &mut self.tokens[self.current..] {
+            if line_start && !matches!(token.token_type, TokenType::Indent(_) | TokenType::NewLine) {
+                break;
             }
+            line_start = matches!(token.token_type, TokenType::NewLine);
 
-            self.files.push(file.clone());
+            if let TokenType::Identifier(name) = &token.token_type {
+                if name.starts_with('.') {
+                    token.token_type = TokenType::Identifier(format!("{}{}", subroutine, name));
+                }
+            }
+        }
+    }
 
-            // read a file
-            let contents = fs::read_to_string(file)
-                .expect(&format!("Unable to read file {}", file));
-            
-            
+    // files
+    /// Reads and scans a file, returning None if it has already been included.
+    /// `token` is the path in the include, which is looked for next to the file it is in, then in each include directory and then in the standard library
+    fn add_file(&mut self, file: &str, token: &Token) -> Result<Option<Vec<Token>>, CompilerError> {
+        let (key, name, contents) = match find_include(file, &token.file, &self.include_dirs, self.sources) {
+            Some(Include::File(path, contents)) => (self.sources.canonical(&path), path.to_string_lossy().to_string(), contents),
+            // library files can't clash with anything on disk
+            Some(Include::Library(name, contents)) => (PathBuf::from(format!("<{}>", name)), name.to_string(), contents.to_string()),
+            None => return Err(CompilerError::from_token(ErrorCode::NoSuchFile(file.to_string()), token, true)),
+        };
+
+        if self.files.contains(&key) {
+            return Ok(None);
+        }
+        self.files.push(key);
 
-            let mut tokens: Vec<Token>;
+        let (mut tokens, mut errors) = scan_tokens(contents, name);
+        self.lexer_errors.append(&mut errors);
 
-            match scan_tokens(contents, file.clone()) {
-                Ok(t) => tokens = t,
-                Err(e) => return Err(e),
-            }
+        tokens.remove(tokens.len() - 1); // remove end of file token
+        Ok(Some(tokens))
+    }
+}
 
-            tokens.remove(tokens.len() - 1); // remove end of file token
-            self.insert(tokens);
-        }
+/// For parsers that never include anything
+struct NoSources;
 
-        return Ok(());
+impl SourceProvider for NoSources {
+    fn read(&self, _: &Path) -> Option<String> {
+        None
     }
 }
 
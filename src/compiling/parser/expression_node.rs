@@ -0,0 +1,417 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::compiling::{compiler::Compiler, error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}};
+
+use super::{number_nodes::{get_number, Imm16, Imm8}, placeholder_node::PlaceholderNode, Node, Parser};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual
+}
+
+/// Returns the operator and its precedence. Higher binds tighter.
+fn binary_operator(token_type: &TokenType) -> Option<(Operator, u8)> {
+    match token_type {
+        TokenType::Equal => Some((Operator::Equal, 1)),
+        TokenType::NotEqual => Some((Operator::NotEqual, 1)),
+        TokenType::Less => Some((Operator::Less, 1)),
+        TokenType::LessEqual => Some((Operator::LessEqual, 1)),
+        TokenType::Greater => Some((Operator::Greater, 1)),
+        TokenType::GreaterEqual => Some((Operator::GreaterEqual, 1)),
+        TokenType::Pipe => Some((Operator::Or, 2)),
+        TokenType::Caret => Some((Operator::Xor, 3)),
+        TokenType::Ampersand => Some((Operator::And, 4)),
+        TokenType::ShiftLeft => Some((Operator::ShiftLeft, 5)),
+        TokenType::ShiftRight => Some((Operator::ShiftRight, 5)),
+        TokenType::Plus => Some((Operator::Add, 6)),
+        TokenType::Minus => Some((Operator::Subtract, 6)),
+        TokenType::Star => Some((Operator::Multiply, 7)),
+        TokenType::Slash => Some((Operator::Divide, 7)),
+        TokenType::Percent => Some((Operator::Modulo, 7)),
+        _ => None
+    }
+}
+
+/// Returns true if the token can be the start of an expression
+pub fn starts_expression(token: &Token) -> bool {
+    matches!(token.token_type, TokenType::Number(_) | TokenType::Identifier(_) | TokenType::OpenParenthesis | TokenType::Minus)
+}
+
+/// Splits the tokens after a macro call into one list of tokens per argument.
+/// Tokens joined by operators or parenthesis belong to the same argument.
+/// A minus with a space before it but not after, like in `jeq x -1 target`, starts a new argument
+pub fn group_arguments(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut arguments: Vec<Vec<Token>> = Vec::new();
+    let mut depth = 0;
+    let mut previous: Option<Token> = None;
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let joins_previous = match &previous {
+            None => false,
+            Some(previous) => {
+                depth > 0
+                    || binary_operator(&previous.token_type).is_some()
+                    || (binary_operator(&token.token_type).is_some() && !is_negative(&token, tokens.peek()))
+                    || matches!(token.token_type, TokenType::CloseParenthesis)
+                    || (matches!(token.token_type, TokenType::OpenParenthesis) && is_function(&previous.token_type))
+            }
+        };
+
+        match token.token_type {
+            TokenType::OpenParenthesis => depth += 1,
+            TokenType::CloseParenthesis => depth -= 1,
+            _ => ()
+        }
+
+        if joins_previous {
+            arguments.last_mut().unwrap().push(token.clone());
+        } else {
+            arguments.push(vec![token.clone()]);
+        }
+
+        previous = Some(token);
+    }
+
+    arguments
+}
+
+/// Checks if a minus is the sign of the value after it rather than a subtraction
+fn is_negative(token: &Token, next: Option<&Token>) -> bool {
+    matches!(token.token_type, TokenType::Minus) && token.spaced && next.is_some_and(|next| !next.spaced)
+}
+
+fn is_function(token_type: &TokenType) -> bool {
+    matches!(token_type, TokenType::Identifier(name) if name == "hi" || name == "lo")
+}
+
+#[derive(Debug)]
+pub enum ExpressionNode {
+    Number(i32),
+    Placeholder(PlaceholderNode),
+    Binary(Box<ExpressionNode>, Operator, Box<ExpressionNode>, Token),
+    HighByte(Box<ExpressionNode>),
+    LowByte(Box<ExpressionNode>),
+    Negative(Box<ExpressionNode>, Token)
+}
+
+impl ExpressionNode {
+    pub fn populate(parser: &mut Parser) -> Result<ExpressionNode, CompilerError> {
+        ExpressionNode::binary(parser, 0)
+    }
+
+    fn binary(parser: &mut Parser, min_precedence: u8) -> Result<ExpressionNode, CompilerError> {
+        let mut left = ExpressionNode::primary(parser)?;
+
+        while let Some((operator, precedence)) = binary_operator(&parser.peek().token_type) {
+            if precedence < min_precedence {
+                break;
+            }
+
+            let token = parser.advance().clone();
+            let right = ExpressionNode::binary(parser, precedence + 1)?;
+            left = ExpressionNode::Binary(Box::new(left), operator, Box::new(right), token);
+        }
+
+        Ok(left)
+    }
+
+    fn primary(parser: &mut Parser) -> Result<ExpressionNode, CompilerError> {
+        match &parser.peek().token_type {
+            TokenType::Number(_) => Ok(ExpressionNode::Number(get_number(parser)?)),
+
+            TokenType::Minus => {
+                let token = parser.advance().clone(); // advance past minus
+                Ok(ExpressionNode::Negative(Box::new(ExpressionNode::primary(parser)?), token))
+            }
+
+            TokenType::Identifier(name) if is_function(&parser.peek().token_type)
+                && matches!(parser.peek_next().token_type, TokenType::OpenParenthesis) => {
+
+                let high = name == "hi";
+                parser.advance(); // advance past function name
+                parser.advance(); // advance past open parenthesis
+
+                let value = Box::new(ExpressionNode::populate(parser)?);
+
+                if !matches!(parser.advance().token_type, TokenType::CloseParenthesis) {
+                    return Err(CompilerError::expected("Close Parenthesis", parser.current(), false));
+                }
+
+                if high {
+                    Ok(ExpressionNode::HighByte(value))
+                } else {
+                    Ok(ExpressionNode::LowByte(value))
+                }
+            }
+
+            TokenType::Identifier(_) => Ok(ExpressionNode::Placeholder(PlaceholderNode::populate(parser)?)),
+
+            TokenType::OpenParenthesis => {
+                parser.advance(); // advance past open parenthesis
+                let value = ExpressionNode::populate(parser)?;
+
+                if !matches!(parser.advance().token_type, TokenType::CloseParenthesis) {
+                    return Err(CompilerError::expected("Close Parenthesis", parser.current(), false));
+                }
+
+                Ok(value)
+            }
+
+            _ => Err(CompilerError::expected("Number, Identifier or Open Parenthesis", parser.peek(), false))
+        }
+    }
+
+    pub fn evaluate(&self, scope: &HashMap<String, Imm16>) -> Result<i32, CompilerError> {
+        match self {
+            ExpressionNode::Number(num) => Ok(*num),
+            ExpressionNode::Placeholder(node) => Ok(node.evaluate(scope)?.value() as i32),
+            ExpressionNode::HighByte(value) => Ok((value.evaluate(scope)? >> 8) & 0xFF),
+            ExpressionNode::LowByte(value) => Ok(value.evaluate(scope)? & 0xFF),
+            ExpressionNode::Negative(value, token) => match value.evaluate(scope)?.checked_neg() {
+                Some(result) => Ok(result),
+                None => Err(CompilerError::from_token(ErrorCode::ExpressionOverflow, token, false)),
+            },
+            ExpressionNode::Binary(left, operator, right, token) => {
+                let left = left.evaluate(scope)?;
+                let right = right.evaluate(scope)?;
+
+                if matches!(operator, Operator::Divide | Operator::Modulo) && right == 0 {
+                    return Err(CompilerError::from_token(ErrorCode::DivisionByZero, token, false));
+                }
+
+                let result = match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide => left.checked_div(right),
+                    Operator::Modulo => left.checked_rem(right),
+                    Operator::And => Some(left & right),
+                    Operator::Or => Some(left | right),
+                    Operator::Xor => Some(left ^ right),
+                    Operator::ShiftLeft => u32::try_from(right).ok().and_then(|r| left.checked_shl(r)),
+                    Operator::ShiftRight => u32::try_from(right).ok().and_then(|r| left.checked_shr(r)),
+                    Operator::Equal => Some((left == right) as i32),
+                    Operator::NotEqual => Some((left != right) as i32),
+                    Operator::Less => Some((left < right) as i32),
+                    Operator::LessEqual => Some((left <= right) as i32),
+                    Operator::Greater => Some((left > right) as i32),
+                    Operator::GreaterEqual => Some((left >= right) as i32),
+                };
+
+                match result {
+                    Some(result) => Ok(result),
+                    None => Err(CompilerError::from_token(ErrorCode::ExpressionOverflow, token, false)),
+                }
+            }
+        }
+    }
+
+    /// Returns every placeholder used in this expression
+    pub fn placeholders(&self) -> Vec<&PlaceholderNode> {
+        match self {
+            ExpressionNode::Number(_) => Vec::new(),
+            ExpressionNode::Placeholder(node) => vec![node],
+            ExpressionNode::HighByte(value) | ExpressionNode::LowByte(value) | ExpressionNode::Negative(value, _) => value.placeholders(),
+            ExpressionNode::Binary(left, _, right, _) => {
+                let mut placeholders = left.placeholders();
+                placeholders.append(&mut right.placeholders());
+                placeholders
+            }
+        }
+    }
+
+    /// Returns the name of every placeholder used in this expression
+    pub fn names(&self) -> Vec<&String> {
+        self.placeholders().into_iter().map(|p| &p.name).collect()
+    }
+
+    /// Returns an error for every placeholder that is not in scope
+    pub fn check_placeholders(&self, scope: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+        self.placeholders().into_iter().filter_map(|p| p.check(scope).err()).collect()
+    }
+}
+
+impl fmt::Display for ExpressionNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionNode::Number(num) => write!(f, "{}", num),
+            ExpressionNode::Placeholder(node) => write!(f, "{}", node),
+            ExpressionNode::HighByte(value) => write!(f, "hi({})", value),
+            ExpressionNode::LowByte(value) => write!(f, "lo({})", value),
+            ExpressionNode::Negative(value, _) => {
+                write!(f, "-")?;
+                write_operand(f, value)
+            }
+            ExpressionNode::Binary(left, operator, right, _) => {
+                write_operand(f, left)?;
+                write!(f, " {} ", operator)?;
+                write_operand(f, right)
+            }
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter<'_>, node: &ExpressionNode) -> fmt::Result {
+    if matches!(node, ExpressionNode::Binary(..)) {
+        write!(f, "({})", node)
+    } else {
+        write!(f, "{}", node)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::And => "&",
+            Operator::Or => "|",
+            Operator::Xor => "^",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// An expression that must fit in 16 bits once every placeholder is known
+#[derive(Debug)]
+pub struct Imm16Expression {
+    expression: ExpressionNode,
+    token: Token,
+    value: Option<Imm16>
+}
+
+impl Imm16Expression {
+    /// The name of the placeholder if that is all the expression is, like the label in `JMP _loop`
+    pub fn placeholder(&self) -> Option<&str> {
+        match &self.expression {
+            ExpressionNode::Placeholder(node) => Some(&node.name),
+            _ => None
+        }
+    }
+
+    pub fn check_placeholders(&self, scope: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+        self.expression.check_placeholders(scope)
+    }
+
+    pub fn resolve(&mut self, scope: &HashMap<String, Imm16>) -> Result<(), CompilerError> {
+        let num = self.expression.evaluate(scope)?;
+        match Imm16::new(num) {
+            Some(n) => {
+                self.value = Some(n);
+                Ok(())
+            },
+            None => Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, Imm16::MIN, Imm16::MAX), &self.token, false)),
+        }
+    }
+}
+
+impl Node for Imm16Expression {
+    fn populate(parser: &mut Parser) -> Result<Imm16Expression, CompilerError> {
+        let token = parser.peek().clone();
+        Ok(Imm16Expression {
+            expression: ExpressionNode::populate(parser)?,
+            token,
+            value: None
+        })
+    }
+
+    fn get_size(&self) -> i32 {
+        2
+    }
+
+    fn compile(&self, compiler: &mut Compiler) {
+        self.value.as_ref().expect("Expressions should be resolved before compiling").compile(compiler)
+    }
+}
+
+impl fmt::Display for Imm16Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+/// An expression that must fit in 8 bits once every placeholder is known
+#[derive(Debug)]
+pub struct Imm8Expression {
+    expression: ExpressionNode,
+    token: Token,
+    value: Option<Imm8>
+}
+
+impl Imm8Expression {
+    /// The value of the expression if it is only a number
+    pub fn number(&self) -> Option<i32> {
+        match self.expression {
+            ExpressionNode::Number(num) => Some(num),
+            _ => None
+        }
+    }
+
+    pub fn check_placeholders(&self, scope: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+        self.expression.check_placeholders(scope)
+    }
+
+    pub fn resolve(&mut self, scope: &HashMap<String, Imm16>) -> Result<(), CompilerError> {
+        let num = self.expression.evaluate(scope)?;
+        match Imm8::new(num) {
+            Some(n) => {
+                self.value = Some(n);
+                Ok(())
+            },
+            None => Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, Imm8::MIN, Imm8::MAX), &self.token, false)),
+        }
+    }
+}
+
+impl Node for Imm8Expression {
+    fn populate(parser: &mut Parser) -> Result<Imm8Expression, CompilerError> {
+        let token = parser.peek().clone();
+        Ok(Imm8Expression {
+            expression: ExpressionNode::populate(parser)?,
+            token,
+            value: None
+        })
+    }
+
+    fn get_size(&self) -> i32 {
+        1
+    }
+
+    fn compile(&self, compiler: &mut Compiler) {
+        self.value.as_ref().expect("Expressions should be resolved before compiling").compile(compiler)
+    }
+}
+
+impl fmt::Display for Imm8Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
@@ -1,29 +1,43 @@
-use crate::compiling::{error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, Instruction};
+use core::fmt;
+use std::collections::HashMap;
 
-use super::{macros::MacroHolder, placeholder_node::{PlaceholderNode, PlaceholderOrImm16Node}, reg_imm_node::RegOrImmNode, register_node::RegisterNode, Node, Parser};
+use crate::compiling::{compiler::{Compiler, ListingEntry}, error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, Instruction, Register};
+
+use super::{expression_node::{group_arguments, starts_expression, Imm16Expression}, macros::MacroHolder, number_nodes::Imm16, reg_imm_node::RegOrImmNode, register_node::RegisterNode, Node, Parser};
 
 #[derive(Debug)]
 pub enum InstructionNode {
     NOP,
-    LW(RegisterNode, Option<PlaceholderOrImm16Node>),
-    SW(RegisterNode, Option<PlaceholderOrImm16Node>),
+    LW(RegisterNode, Option<Imm16Expression>),
+    SW(RegisterNode, Option<Imm16Expression>),
     MW(RegisterNode, RegOrImmNode),
     PUSH(RegOrImmNode),
     POP(RegisterNode),
-    LDA(PlaceholderOrImm16Node),
-    JMP(Option<PlaceholderNode>),
-    JZ(RegisterNode, Option<PlaceholderNode>),
-    JC(Option<PlaceholderNode>),
+    LDA(Imm16Expression),
+    JMP(Option<Imm16Expression>),
+    JZ(RegisterNode, Option<Imm16Expression>),
+    JC(Option<Imm16Expression>),
     ADD(RegisterNode, RegOrImmNode),
     SUB(RegisterNode, RegOrImmNode),
     OUT(RegOrImmNode),
     HLT,
 
+    /// Pushes the return address, high byte first, then jumps to the subroutine.
+    /// Compiles to PUSH hi, PUSH lo, JMP target
+    CALL(Imm16Expression),
+    /// Pops the return address into H and L and jumps to it. H and L are clobbered,
+    /// everything else, flags included, is left alone. Compiles to POP L, POP H, JMP
+    RET,
+
     Macro(MacroHolder),
 
-    DEF(String)
+    /// The name of the label and the token it came from
+    DEF(String, Token)
 }
 
+/// Bytes produced by CALL: two PUSH immediates and a JMP immediate
+const CALL_SIZE: i32 = 2 + 2 + 3;
+
 impl Node for InstructionNode {
     fn populate(parser: &mut Parser) -> Result<InstructionNode, CompilerError> {
         let token = parser.advance();
@@ -32,10 +46,10 @@ impl Node for InstructionNode {
             TokenType::Instruction(Instruction::NOP) => Ok(InstructionNode::NOP),
             TokenType::Instruction(Instruction::LW) => {
                 let register = RegisterNode::populate(parser);
-                let number: Option<PlaceholderOrImm16Node>;
+                let number: Option<Imm16Expression>;
 
-                if matches!(parser.peek().token_type, TokenType::Number(_) | TokenType::Identifier(_)) {
-                    number = Some(PlaceholderOrImm16Node::populate(parser)?)
+                if starts_expression(parser.peek()) {
+                    number = Some(Imm16Expression::populate(parser)?)
                 } else {
                     number = None;
                 }
@@ -44,10 +58,10 @@ impl Node for InstructionNode {
             },
             TokenType::Instruction(Instruction::SW) => {
                 let register = RegisterNode::populate(parser);
-                let number: Option<PlaceholderOrImm16Node>;
+                let number: Option<Imm16Expression>;
 
-                if matches!(parser.peek().token_type, TokenType::Number(_) | TokenType::Identifier(_)) {
-                    number = Some(PlaceholderOrImm16Node::populate(parser)?)
+                if starts_expression(parser.peek()) {
+                    number = Some(Imm16Expression::populate(parser)?)
                 } else {
                     number = None;
                 }
@@ -62,25 +76,25 @@ impl Node for InstructionNode {
             },
             TokenType::Instruction(Instruction::PUSH) => Ok(InstructionNode::PUSH(RegOrImmNode::populate(parser)?)),
             TokenType::Instruction(Instruction::POP) => Ok(InstructionNode::POP(RegisterNode::populate(parser)?)),
-            TokenType::Instruction(Instruction::LDA) => Ok(InstructionNode::LDA(PlaceholderOrImm16Node::populate(parser)?)),
+            TokenType::Instruction(Instruction::LDA) => Ok(InstructionNode::LDA(Imm16Expression::populate(parser)?)),
             TokenType::Instruction(Instruction::JMP) => {
-                if matches!(parser.peek().token_type, TokenType::Identifier(_)) {
-                    Ok(InstructionNode::JMP(Some(PlaceholderNode::populate(parser)?)))
+                if starts_expression(parser.peek()) {
+                    Ok(InstructionNode::JMP(Some(Imm16Expression::populate(parser)?)))
                 } else {
                     Ok(InstructionNode::JMP(None))
                 }
             },
             TokenType::Instruction(Instruction::JZ) => {
                 let register = RegisterNode::populate(parser)?;
-                if matches!(parser.peek().token_type, TokenType::Identifier(_)) {
-                    Ok(InstructionNode::JZ(register, Some(PlaceholderNode::populate(parser)?)))
+                if starts_expression(parser.peek()) {
+                    Ok(InstructionNode::JZ(register, Some(Imm16Expression::populate(parser)?)))
                 } else {
                     Ok(InstructionNode::JZ(register, None))
                 }
             },
             TokenType::Instruction(Instruction::JC) => {
-                if matches!(parser.peek().token_type, TokenType::Identifier(_)) {
-                    Ok(InstructionNode::JC(Some(PlaceholderNode::populate(parser)?)))
+                if starts_expression(parser.peek()) {
+                    Ok(InstructionNode::JC(Some(Imm16Expression::populate(parser)?)))
                 } else {
                     Ok(InstructionNode::JC(None))
                 }
@@ -94,9 +108,13 @@ impl Node for InstructionNode {
             TokenType::Instruction(Instruction::HLT) => 
                 Ok(InstructionNode::HLT),
 
+            TokenType::Call => Ok(InstructionNode::CALL(Imm16Expression::populate(parser)?)),
+            TokenType::Return => Ok(InstructionNode::RET),
+
             TokenType::Def => {
-                if let TokenType::Identifier(name) = &parser.advance().token_type {
-                    Ok(InstructionNode::DEF(name.clone()))
+                let label = parser.advance().clone();
+                if let TokenType::Identifier(name) = &label.token_type {
+                    Ok(InstructionNode::DEF(name.clone(), label))
                 } else {
                     Err(CompilerError::from_token(
                         ErrorCode::ExpectedButFound("Identifier".to_string(), parser.current().token_type.clone()), parser.current(), false
@@ -120,7 +138,7 @@ impl Node for InstructionNode {
                 while !matches!(parser.peek().token_type, TokenType::NewLine | TokenType::EndOfFile) {
                     arguments.push(parser.advance().clone());
                 }
-                Ok(InstructionNode::Macro(MacroHolder::Placeholder(macro_name, arguments, token)))
+                Ok(InstructionNode::Macro(MacroHolder::Placeholder(macro_name, group_arguments(arguments), token)))
             }
 
             _ => {
@@ -158,7 +176,9 @@ impl Node for InstructionNode {
                     None => 1
                 }
             },
-            Self::DEF(_) => 0,
+            Self::CALL(_) => CALL_SIZE,
+            Self::RET => 3,
+            Self::DEF(..) => 0,
             Self::Macro(holder) => {
                 match holder {
                     MacroHolder::Placeholder(_, _, _) => panic!("Cannot get the size of a macro placeholder"),
@@ -168,7 +188,7 @@ impl Node for InstructionNode {
         }
     }
     
-    fn compile(&self, compiler: &mut crate::compiling::compiler::Compiler) {
+    fn compile(&self, compiler: &mut Compiler) {
         match self {
             InstructionNode::NOP => compiler.first_byte(Instruction::NOP, false, None),
             
@@ -233,7 +253,7 @@ impl Node for InstructionNode {
                     None => compiler.first_byte(instruction, false, None),
                     Some(val) => {
                         compiler.first_byte(instruction, true, None);
-                        val.compile(compiler, false)
+                        val.compile(compiler)
                     }
                 }
             }
@@ -243,13 +263,31 @@ impl Node for InstructionNode {
                     None => compiler.first_byte(Instruction::JZ, false, Some(reg.0)),
                     Some(val) => {
                         compiler.first_byte(Instruction::JZ, true, Some(reg.0));
-                        val.compile(compiler, false)
+                        val.compile(compiler)
                     }
                 }
             }
             
             InstructionNode::HLT => compiler.first_byte(Instruction::HLT, false, None),
 
+            InstructionNode::CALL(target) => {
+                let [high, low] = (compiler.bytes.len() as u16 + CALL_SIZE as u16).to_be_bytes();
+
+                compiler.first_byte(Instruction::PUSH, true, None);
+                compiler.add_byte(high);
+                compiler.first_byte(Instruction::PUSH, true, None);
+                compiler.add_byte(low);
+                compiler.first_byte(Instruction::JMP, true, None);
+                target.compile(compiler)
+            }
+
+            InstructionNode::RET => {
+                // popped in the opposite order CALL pushed them
+                compiler.first_byte(Instruction::POP, false, Some(Register::L));
+                compiler.first_byte(Instruction::POP, false, Some(Register::H));
+                compiler.first_byte(Instruction::JMP, false, None);
+            }
+
             InstructionNode::Macro(holder) => {
                 match holder {
                     MacroHolder::Placeholder(_, _, _) => panic!("Cannot compile a macro placeholder"),
@@ -257,7 +295,126 @@ impl Node for InstructionNode {
                 }
             },
 
-            InstructionNode::DEF(_) => {} // do nothing.
+            InstructionNode::DEF(name, _) => {
+                compiler.listing.push(ListingEntry::Label(name.clone(), compiler.bytes.len() as u16))
+            }
+        }
+    }
+}
+
+impl InstructionNode {
+    /// Returns an error for every placeholder used by this instruction that is not in scope
+    pub fn check_placeholders(&self, scope: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+        match self {
+            InstructionNode::LW(_, Some(imm)) |
+                InstructionNode::SW(_, Some(imm)) |
+                InstructionNode::LDA(imm) |
+                InstructionNode::JMP(Some(imm)) |
+                InstructionNode::JZ(_, Some(imm)) |
+                InstructionNode::JC(Some(imm)) |
+                InstructionNode::CALL(imm) => imm.check_placeholders(scope),
+
+            InstructionNode::MW(_, reg_imm) |
+                InstructionNode::ADD(_, reg_imm) |
+                InstructionNode::SUB(_, reg_imm) |
+                InstructionNode::PUSH(reg_imm) |
+                InstructionNode::OUT(reg_imm) => reg_imm.check_placeholders(scope),
+
+            InstructionNode::Macro(MacroHolder::Macro(m)) => m.check_placeholders(),
+            InstructionNode::Macro(MacroHolder::Placeholder(..)) => panic!("All macros should be populated by now"),
+
+            _ => Vec::new()
+        }
+    }
+
+    /// Calculates the value of every expression now that all placeholders are known
+    pub fn resolve(&mut self, scope: &HashMap<String, Imm16>) -> Result<(), Vec<CompilerError>> {
+        let result = match self {
+            InstructionNode::LW(_, Some(imm)) |
+                InstructionNode::SW(_, Some(imm)) |
+                InstructionNode::LDA(imm) |
+                InstructionNode::JMP(Some(imm)) |
+                InstructionNode::JZ(_, Some(imm)) |
+                InstructionNode::JC(Some(imm)) |
+                InstructionNode::CALL(imm) => imm.resolve(scope),
+
+            InstructionNode::MW(_, reg_imm) |
+                InstructionNode::ADD(_, reg_imm) |
+                InstructionNode::SUB(_, reg_imm) |
+                InstructionNode::PUSH(reg_imm) |
+                InstructionNode::OUT(reg_imm) => reg_imm.resolve(scope),
+
+            InstructionNode::Macro(MacroHolder::Macro(m)) => return m.resolve(),
+            InstructionNode::Macro(MacroHolder::Placeholder(..)) => panic!("All macros should be populated by now"),
+
+            _ => Ok(())
+        };
+
+        result.map_err(|e| vec![e])
+    }
+}
+
+/// Compiles a list of instructions, recording where each one came from
+pub fn compile_instructions(instructions: &[(Token, InstructionNode)], compiler: &mut Compiler) {
+    for (token, instruction) in instructions {
+        let address = compiler.bytes.len();
+        instruction.compile(compiler);
+
+        // macros and labels add their own listing entries
+        if !matches!(instruction, InstructionNode::Macro(_) | InstructionNode::DEF(..)) {
+            compiler.add_source_line(address, token, instruction.to_string());
+        }
+    }
+}
+
+impl fmt::Display for InstructionNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstructionNode::NOP | InstructionNode::HLT => write!(f, "{:?}", node_to_instr(self)),
+
+            InstructionNode::LW(reg, imm) | InstructionNode::SW(reg, imm) => {
+                write!(f, "{:?} {}", node_to_instr(self), reg)?;
+                if let Some(imm) = imm {
+                    write!(f, " {}", imm)?;
+                }
+                Ok(())
+            }
+
+            InstructionNode::MW(reg, reg_imm) |
+                InstructionNode::ADD(reg, reg_imm) |
+                InstructionNode::SUB(reg, reg_imm) => write!(f, "{:?} {} {}", node_to_instr(self), reg, reg_imm),
+
+            InstructionNode::PUSH(reg_imm) | InstructionNode::OUT(reg_imm) => 
+                write!(f, "{:?} {}", node_to_instr(self), reg_imm),
+
+            InstructionNode::POP(reg) => write!(f, "POP {}", reg),
+            InstructionNode::LDA(imm) => write!(f, "LDA {}", imm),
+
+            InstructionNode::JMP(imm) | InstructionNode::JC(imm) => {
+                write!(f, "{:?}", node_to_instr(self))?;
+                if let Some(imm) = imm {
+                    write!(f, " {}", imm)?;
+                }
+                Ok(())
+            }
+
+            InstructionNode::JZ(reg, imm) => {
+                write!(f, "JZ {}", reg)?;
+                if let Some(imm) = imm {
+                    write!(f, " {}", imm)?;
+                }
+                Ok(())
+            }
+
+            InstructionNode::CALL(target) => write!(f, "CALL {}", target),
+            InstructionNode::RET => write!(f, "RET"),
+
+            InstructionNode::Macro(holder) => match holder {
+                MacroHolder::Placeholder(name, _, _) => write!(f, "{}", name),
+                MacroHolder::Macro(m) => write!(f, "{}", m),
+            },
+
+            InstructionNode::DEF(name, _) => write!(f, "DEF {}", name),
         }
     }
 }
@@ -278,7 +435,8 @@ fn node_to_instr(node: &InstructionNode) -> Instruction {
         InstructionNode::SUB(_, _) => Instruction::SUB,
         InstructionNode::OUT(_) => Instruction::OUT,
         InstructionNode::HLT => Instruction::HLT,
+        InstructionNode::CALL(_) | InstructionNode::RET => panic!("Cannot convert a pseudo instruction node to an opcode"),
         InstructionNode::Macro(_) => panic!("Cannot convert a macro instruction node to an opcode"),
-        InstructionNode::DEF(_) => panic!("Cannot convert a def instruction node to an opcode"),
+        InstructionNode::DEF(..) => panic!("Cannot convert a def instruction node to an opcode"),
     }
 }
\ No newline at end of file
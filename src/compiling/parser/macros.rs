@@ -1,13 +1,17 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap, fmt};
 
-use crate::compiling::{compiler::Compiler, error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, parser::subroutine_node::get_instructions};
-use super::{instruction_node::InstructionNode, number_nodes::Imm16, Node, Parser};
+use crate::compiling::{ADDRESS_SPACE, compiler::{Compiler, ListingEntry}, error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, parser::subroutine_node::get_instructions, symbols::{Symbol, SymbolKind}};
+use super::{instruction_node::{compile_instructions, InstructionNode}, number_nodes::Imm16, Node, Parser};
 
 #[derive(Debug)]
 pub struct Macro {
     pub name: String,
+    pub token: Token,
     tokens: Vec<Token>,
-    arguments: HashMap<String, usize>
+    arguments: HashMap<String, usize>,
+    /// labels defined inside the macro, these are renamed every time the macro is used
+    labels: Vec<String>,
+    expansions: Cell<usize>
 }
 
 impl Macro {
@@ -24,7 +28,8 @@ impl Macro {
 
         // expect a silly lil guy (identifier)
         let name: String;
-        if let TokenType::Identifier(n) = &parser.advance().token_type {
+        let token = parser.advance().clone();
+        if let TokenType::Identifier(n) = &token.token_type {
             name = n.clone();
         } else {
             return Err(CompilerError::expected("Identifier", parser.current(), true));
@@ -91,36 +96,89 @@ impl Macro {
             }
         }
 
+        let mut labels: Vec<String> = Vec::new();
+        let mut label_tokens: Vec<&Token> = Vec::new();
+        for pair in tokens.windows(2) {
+            if let (TokenType::Def, TokenType::Identifier(label)) = (&pair[0].token_type, &pair[1].token_type) {
+                if arguments.contains_key(label) {
+                    continue;
+                }
+
+                if let Some(index) = labels.iter().position(|l| l == label) {
+                    return Err(CompilerError::from_token(ErrorCode::DuplicateSymbol(label.clone()), &pair[1], true)
+                        .with_note("first defined here", label_tokens[index]));
+                }
+
+                labels.push(label.clone());
+                label_tokens.push(&pair[1]);
+            }
+        }
+
         Ok(Macro {
             name,
+            token,
             tokens,
-            arguments
+            arguments,
+            labels,
+            expansions: Cell::new(0)
         })
     }
 }
 
+/// How many macros deep an expansion can go before giving up
+const MAX_MACRO_DEPTH: usize = 32;
+
+/// How many instructions macros can expand to in the whole program. Every instruction is at least a byte,
+/// so more than this can never fit in the address space. Without it macros that each use the next one a few times grow exponentially
+const MAX_EXPANDED_INSTRUCTIONS: usize = ADDRESS_SPACE;
+
 #[derive(Debug)]
 pub enum MacroHolder {
-    Placeholder(String, Vec<Token>, Token),
+    Placeholder(String, Vec<Vec<Token>>, Token),
     Macro(MacroNode)
 }
 
 #[derive(Debug)]
 pub struct MacroNode {
-    instructions: Vec<InstructionNode>,
+    pub name: String,
+    pub arguments: Vec<Vec<Token>>,
+    token: Token,
+    pub instructions: Vec<(Token, InstructionNode)>,
     placeholders: HashMap<String, Imm16>
 }
 
 // similar functions to Node
 impl MacroNode {
-    pub fn populate(definition: &Macro, args: &Vec<Token>) -> Result<MacroNode, Vec<CompilerError>> {
+    /// Expands a macro. `chain` holds the names of the macros currently being expanded
+    /// and `expanded` counts the instructions every expansion so far has produced
+    pub fn populate(definition: &Macro, args: &Vec<Vec<Token>>, token: &Token, macros: &HashMap<String, Macro>, chain: &[String], expanded: &Cell<usize>) -> Result<MacroNode, Vec<CompilerError>> {
+        // give this expansion its own copy of the labels
+        definition.expansions.set(definition.expansions.get() + 1);
+        let expansion = definition.expansions.get();
+
         // replace arguments
         let mut tokens: Vec<Token> = Vec::new();
 
         for token in &definition.tokens {
             if let TokenType::Identifier(identifier) = &token.token_type {
                 if definition.arguments.contains_key(identifier) {
-                    tokens.push(args.get(*definition.arguments.get(identifier).unwrap()).unwrap().clone());
+                    let arg = args.get(*definition.arguments.get(identifier).unwrap()).unwrap();
+
+                    // the argument is spaced like the name it replaces, so `-x` is still a negative number
+                    if arg.len() == 1 {
+                        tokens.push(Token { spaced: token.spaced, ..arg[0].clone() });
+                    } else {
+                        // keep expressions together
+                        tokens.push(Token { spaced: token.spaced, ..Token::at(TokenType::OpenParenthesis, &arg[0]) });
+                        tokens.extend(arg.iter().cloned());
+                        tokens.push(Token::at(TokenType::CloseParenthesis, arg.last().unwrap()));
+                    }
+                    continue;
+                }
+
+                if definition.labels.contains(identifier) {
+                    let label = format!("{}#{}.{}", definition.name, expansion, identifier);
+                    tokens.push(Token::at(TokenType::Identifier(label), token));
                     continue;
                 }
             }
@@ -129,26 +187,23 @@ impl MacroNode {
 
         tokens.push(Token::new(TokenType::EndOfFile, -1, "unknown".to_string()));
 
-        let mut parser = Parser::new(tokens, String::from("main.bread"));
+        let mut parser = Parser::from_tokens(tokens, String::from("main.bread"));
 
-        let instructions = get_instructions(&mut parser)?;
+        let mut instructions = get_instructions(&mut parser)?;
 
-        let mut errors: Vec<CompilerError> = Vec::new();
-        for instruction in &instructions {
-            if let InstructionNode::Macro(holder) = instruction {
-                if let MacroHolder::Placeholder(_, _, token) = holder {
-                    errors.push(CompilerError::from_token(ErrorCode::MacroCallsMacro, token, true))
-                } else {
-                    panic!("Macro call inside of macro was populated")
-                }
-            }
+        expanded.set(expanded.get() + instructions.len());
+        if expanded.get() > MAX_EXPANDED_INSTRUCTIONS {
+            return Err(vec![CompilerError::from_token(ErrorCode::MacroTooLarge(MAX_EXPANDED_INSTRUCTIONS), token, true)]);
         }
 
-        if !errors.is_empty() {
-            return Err(errors)
-        }
+        let mut chain = chain.to_vec();
+        chain.push(definition.name.clone());
+        populate_macros(&mut instructions, macros, &chain, expanded)?;
 
         Ok(MacroNode {
+            name: definition.name.clone(),
+            arguments: args.clone(),
+            token: token.clone(),
             instructions,
             placeholders: HashMap::new()
         })
@@ -157,7 +212,7 @@ impl MacroNode {
     pub fn get_size(&self) -> i32 {
         let mut size = 0;
         
-        for node in &self.instructions {
+        for (_, node) in &self.instructions {
             size += node.get_size();
         }
 
@@ -165,20 +220,115 @@ impl MacroNode {
     }
 
     pub fn compile(&self, compiler: &mut Compiler) {
-        compiler.scope = self.placeholders.clone();
-        for instructions in &self.instructions {
-            instructions.compile(compiler);
-        }
+        compiler.listing.push(ListingEntry::Macro(self.to_string(), self.token.clone()));
+        compile_instructions(&self.instructions, compiler);
     }
 
-    pub fn calculate_placeholders(&mut self, position: &mut u16, placeholders: &HashMap<String, Imm16>) {
+    pub fn calculate_placeholders(&mut self, position: &mut u16, placeholders: &HashMap<String, Imm16>, symbols: &mut Vec<Symbol>) {
+        let initial_position = *position;
+
         self.placeholders = placeholders.clone();
-        for instruction in &self.instructions {
-            if let InstructionNode::DEF(name) = instruction {
+        for (token, instruction) in &self.instructions {
+            if let InstructionNode::DEF(name, _) = instruction {
+                symbols.push(Symbol::new(name, *position, SymbolKind::Label, token));
                 self.placeholders.insert(name.clone(), Imm16::from(*position));
             } else {
-                *position += instruction.get_size() as u16;
+                *position = position.wrapping_add(instruction.get_size() as u16);
+            }
+        }
+
+        // now macros inside this macro
+        *position = initial_position;
+
+        for (_, instruction) in &mut self.instructions {
+            if let InstructionNode::Macro(MacroHolder::Macro(node)) = instruction {
+                node.calculate_placeholders(position, &self.placeholders, symbols);
+            } else {
+                *position = position.wrapping_add(instruction.get_size() as u16);
             }
         }
     }
-}
\ No newline at end of file
+
+    pub fn check_placeholders(&self) -> Vec<CompilerError> {
+        self.instructions.iter()
+            .flat_map(|(_, instruction)| instruction.check_placeholders(&self.placeholders))
+            .map(|e| e.with_note(&format!("in this use of macro {}", self.name), &self.token))
+            .collect()
+    }
+
+    pub fn resolve(&mut self) -> Result<(), Vec<CompilerError>> {
+        let mut errors: Vec<CompilerError> = Vec::new();
+
+        for (_, instruction) in &mut self.instructions {
+            if let Err(e) = instruction.resolve(&self.placeholders) {
+                errors.extend(e.into_iter().map(|e| e.with_note(&format!("in this use of macro {}", self.name), &self.token)));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Replaces every macro call in instructions with the expanded macro
+pub fn populate_macros(instructions: &mut [(Token, InstructionNode)], macros: &HashMap<String, Macro>, chain: &[String], expanded: &Cell<usize>) -> Result<(), Vec<CompilerError>> {
+    let mut errors: Vec<CompilerError> = Vec::new();
+
+    for (_, instruction) in instructions.iter_mut() {
+        if let InstructionNode::Macro(MacroHolder::Placeholder(name, args, token)) = instruction {
+            if chain.contains(name) {
+                let mut cycle = chain[chain.iter().position(|n| n == name).unwrap()..].to_vec();
+                cycle.push(name.clone());
+                errors.push(CompilerError::from_token(ErrorCode::RecursiveMacro(cycle), token, true));
+                continue;
+            }
+
+            if chain.len() >= MAX_MACRO_DEPTH {
+                errors.push(CompilerError::from_token(ErrorCode::MacroTooDeep(MAX_MACRO_DEPTH), token, true));
+                continue;
+            }
+
+            if let Some(m) = macros.get(name) {
+                if args.len() != m.arguments.len() {
+                    let code = ErrorCode::WrongArgumentCount(name.clone(), m.arguments.len(), args.len());
+                    errors.push(CompilerError::from_token(code, token, false).with_note("macro defined here", &m.token));
+                    continue;
+                }
+
+                match MacroNode::populate(m, args, token, macros, chain, expanded) {
+                    Ok(node) => *instruction = InstructionNode::Macro(MacroHolder::Macro(node)),
+                    Err(e) => errors.extend(e.into_iter().map(|e| e.with_note(&format!("in this use of macro {}", name), token))),
+                }
+
+                // the rest would only fail the same way
+                if expanded.get() > MAX_EXPANDED_INSTRUCTIONS {
+                    return Err(errors);
+                }
+            } else {
+                errors.push(CompilerError::from_token(ErrorCode::NoSuchMacro(name.clone()), token, false));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+impl fmt::Display for MacroNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.arguments {
+            write!(f, " ")?;
+            for token in arg {
+                write!(f, "{}", token.token_type)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use core::fmt;
+
 use crate::compiling::{error_handler::{CompilerError, ErrorCode}, lexer::TokenType};
 
 use super::{Node, Parser};
@@ -6,15 +8,17 @@ use super::{Node, Parser};
 pub struct Imm8(u8);
 
 impl Imm8 {
+    /// Negative numbers down to -128 are stored as two's complement
+    pub const MIN: i32 = i8::MIN as i32;
+    pub const MAX: i32 = u8::MAX as i32;
+
     pub fn new(value: i32) -> Option<Imm8> {
-        match u8::try_from(value) {
-            Ok(n) => Some(Imm8(n)),
-            Err(_) => None,
+        if (Imm8::MIN..=Imm8::MAX).contains(&value) {
+            Some(Imm8(value as u8))
+        } else {
+            None
         }
     }
-    pub fn from_imm16(value: Imm16) -> Imm8 {
-        Imm8::new(value.0 as i32).unwrap()
-    }
 }
 
 impl Node for Imm8 {
@@ -22,7 +26,7 @@ impl Node for Imm8 {
         let num = get_number(parser)?;
         match Imm8::new(num) {
             Some(n) => Ok(n),
-            None => Err(CompilerError::from_token(ErrorCode::NumberTooBig(num), parser.current(), false)),
+            None => Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, Imm8::MIN, Imm8::MAX), parser.current(), false)),
         }
     }
 
@@ -39,15 +43,23 @@ impl Node for Imm8 {
 pub struct Imm16(u16);
 
 impl Imm16 {
+    /// Negative numbers down to -32768 are stored as two's complement
+    pub const MIN: i32 = i16::MIN as i32;
+    pub const MAX: i32 = u16::MAX as i32;
+
     pub fn new(value: i32) -> Option<Imm16> {
-        match u16::try_from(value) {
-            Ok(n) => Some(Imm16(n)),
-            Err(_) => None,
+        if (Imm16::MIN..=Imm16::MAX).contains(&value) {
+            Some(Imm16(value as u16))
+        } else {
+            None
         }
     }
     pub fn from(value: u16) -> Imm16 {
         Imm16(value)
     }
+    pub fn value(&self) -> u16 {
+        self.0
+    }
 }
 
 impl Node for Imm16 {
@@ -55,7 +67,7 @@ impl Node for Imm16 {
         let num = get_number(parser)?;
         match Imm16::new(num) {
             Some(n) => Ok(n),
-            None => Err(CompilerError::from_token(ErrorCode::NumberTooBig(num), parser.current(), false)),
+            None => Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, Imm16::MIN, Imm16::MAX), parser.current(), false)),
         }
     }
 
@@ -68,28 +80,89 @@ impl Node for Imm16 {
     }
 }
 
-fn get_number(parser: &mut Parser) -> Result<i32, CompilerError> {
+impl fmt::Display for Imm8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Imm16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub(super) fn get_number(parser: &mut Parser) -> Result<i32, CompilerError> {
     let token = parser.advance();
     if let TokenType::Number(num) = &token.token_type {
-        let num = num.replace("_", "");
-        let result;
-        if num.starts_with("0x") {
-            // hexadecimal
-            result = i32::from_str_radix(&num[2..], 16);
-        } else if num.starts_with("0b") {
-            // binary
-            result = i32::from_str_radix(&num[2..], 2);
-        } else {
-            // decimal
-            result = i32::from_str_radix(&num, 10);
-        }
-
-        return match result {
-            Ok(num) => Ok(num),
-            Err(_) => {
+        return match parse_number(num) {
+            Some(num) => Ok(num),
+            None => {
                 Err(CompilerError::from_token(ErrorCode::InvalidNumber, token, false))
             }
         }
     }
     Err(CompilerError::expected("Number", token, false))
-}
\ No newline at end of file
+}
+
+/// Parses a decimal, hexadecimal (0x), octal (0o), binary (0b) or character ('A') literal.
+/// A - in front makes it negative, which is only used for numbers from the command line
+pub fn parse_number(num: &str) -> Option<i32> {
+    if let Some(positive) = num.strip_prefix('-') {
+        return parse_number(positive).filter(|_| !positive.starts_with('-')).map(|n| -n);
+    }
+
+    if let Some(literal) = num.strip_prefix('\'').and_then(|n| n.strip_suffix('\'')) {
+        return parse_char(literal);
+    }
+
+    let num = num.replace("_", "").to_lowercase();
+    let (digits, radix) = if let Some(digits) = num.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = num.strip_prefix("0o") {
+        (digits, 8)
+    } else if let Some(digits) = num.strip_prefix("0b") {
+        (digits, 2)
+    } else {
+        (num.as_str(), 10)
+    };
+
+    // from_str_radix would allow a sign after the prefix
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+
+    i32::from_str_radix(digits, radix).ok()
+}
+
+/// The value of what is inside the quotes of a character literal: one character below 256 or an escape
+fn parse_char(literal: &str) -> Option<i32> {
+    let mut chars = literal.chars();
+
+    let value = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n' as u32,
+            't' => '\t' as u32,
+            'r' => '\r' as u32,
+            '0' => 0,
+            '\\' => '\\' as u32,
+            '\'' => '\'' as u32,
+            '"' => '"' as u32,
+            'x' => {
+                let hex: String = chars.by_ref().collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                u32::from_str_radix(&hex, 16).ok()?
+            }
+            _ => return None,
+        },
+        c => c as u32,
+    };
+
+    if chars.next().is_some() || value > 0xFF {
+        return None;
+    }
+
+    Some(value as i32)
+}
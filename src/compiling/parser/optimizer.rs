@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiling::{lexer::Token, Register};
+
+use super::{instruction_node::InstructionNode, macros::MacroHolder, reg_imm_node::RegOrImmNode, register_node::RegisterNode, subroutine_node::SubroutineNode};
+
+/// A change to one of the instructions, by its position in the program with every macro expanded
+enum Edit {
+    Remove,
+    /// Turns `PUSH imm` into `MW reg imm`
+    PushToMove(Register),
+}
+
+/// Rewrites redundant sequences until there are none left. Labels are never moved or removed,
+/// and nothing is done across a label since other code can jump to it.
+///
+/// - `PUSH r` then `POP r` is removed
+/// - `PUSH imm` then `POP r` becomes `MW r imm`
+/// - `MW r r` is removed
+/// - a jump to the address right after it is removed
+/// - `LDA x` is removed if H and L already hold x
+/// - `ADD r 0` is removed if the carry it clears is never read
+pub fn optimize(instructions: &mut Vec<(Token, InstructionNode)>) {
+    loop {
+        let edits = {
+            let mut leaves: Vec<(&Token, &InstructionNode)> = Vec::new();
+            flatten(instructions, &mut leaves);
+            find_edits(&leaves.into_iter().map(|(_, instruction)| instruction).collect::<Vec<_>>())
+        };
+
+        if edits.is_empty() {
+            break;
+        }
+
+        apply(instructions, &mut 0, &edits);
+    }
+}
+
+/// Finds a jump or call to something other than a subroutine or label, like `JMP 0x0010`.
+/// Optimizing moves code, so the address it jumps to would no longer be the same instruction
+pub fn unlabelled_jump(subroutines: &[SubroutineNode]) -> Option<&Token> {
+    let mut leaves: Vec<(&Token, &InstructionNode)> = Vec::new();
+    for subroutine in subroutines {
+        flatten(subroutine.instructions(), &mut leaves);
+    }
+
+    let labels: HashSet<&str> = subroutines.iter().map(|s| s.name.as_str())
+        .chain(leaves.iter().filter_map(|(_, leaf)| match leaf {
+            InstructionNode::DEF(name, _) => Some(name.as_str()),
+            _ => None,
+        }))
+        .collect();
+
+    leaves.into_iter()
+        .find(|(_, leaf)| match leaf {
+            InstructionNode::JMP(Some(target)) | InstructionNode::JZ(_, Some(target)) |
+                InstructionNode::JC(Some(target)) | InstructionNode::CALL(target) => !target.placeholder().is_some_and(|name| labels.contains(name)),
+            _ => false,
+        })
+        .map(|(token, _)| token)
+}
+
+/// Every instruction in the order it is compiled in, with macros replaced by their instructions
+fn flatten<'a>(instructions: &'a [(Token, InstructionNode)], leaves: &mut Vec<(&'a Token, &'a InstructionNode)>) {
+    for (token, instruction) in instructions {
+        match instruction {
+            InstructionNode::Macro(MacroHolder::Macro(node)) => flatten(&node.instructions, leaves),
+            InstructionNode::Macro(MacroHolder::Placeholder(_, _, _)) => panic!("All macros should be populated by now"),
+            _ => leaves.push((token, instruction)),
+        }
+    }
+}
+
+/// Makes the edits, counting instructions in the same order as `flatten`
+fn apply(instructions: &mut Vec<(Token, InstructionNode)>, index: &mut usize, edits: &HashMap<usize, Edit>) {
+    instructions.retain_mut(|(_, instruction)| {
+        if let InstructionNode::Macro(MacroHolder::Macro(node)) = instruction {
+            apply(&mut node.instructions, index, edits);
+            return true;
+        }
+
+        let edit = edits.get(index);
+        *index += 1;
+
+        match edit {
+            None => true,
+            Some(Edit::Remove) => false,
+            Some(Edit::PushToMove(register)) => {
+                if let InstructionNode::PUSH(value) = std::mem::replace(instruction, InstructionNode::NOP) {
+                    *instruction = InstructionNode::MW(RegisterNode(*register), value);
+                }
+                true
+            }
+        }
+    })
+}
+
+fn find_edits(leaves: &[&InstructionNode]) -> HashMap<usize, Edit> {
+    let mut edits: HashMap<usize, Edit> = HashMap::new();
+
+    for i in 0..leaves.len() {
+        if edits.contains_key(&i) {
+            continue;
+        }
+        let next = leaves.get(i + 1).filter(|_| !edits.contains_key(&(i + 1)));
+
+        match (leaves[i], next) {
+            (InstructionNode::PUSH(RegOrImmNode::Register(pushed)), Some(InstructionNode::POP(popped))) if pushed.0 == popped.0 => {
+                edits.insert(i, Edit::Remove);
+                edits.insert(i + 1, Edit::Remove);
+            }
+            (InstructionNode::PUSH(RegOrImmNode::Immediate(_)), Some(InstructionNode::POP(popped))) => {
+                edits.insert(i, Edit::PushToMove(popped.0));
+                edits.insert(i + 1, Edit::Remove);
+            }
+            (InstructionNode::MW(to, RegOrImmNode::Register(from)), _) if to.0 == from.0 => {
+                edits.insert(i, Edit::Remove);
+            }
+            (InstructionNode::JMP(Some(target)) | InstructionNode::JZ(_, Some(target)) | InstructionNode::JC(Some(target)), _)
+                if target.placeholder().is_some_and(|label| jumps_to_next(leaves, i, label)) => {
+                edits.insert(i, Edit::Remove);
+            }
+            (InstructionNode::LDA(_), _) if loads_same_address(leaves, i) => {
+                edits.insert(i, Edit::Remove);
+            }
+            (InstructionNode::ADD(_, RegOrImmNode::Immediate(value)), _) if value.number() == Some(0) && carry_unused(leaves, i) => {
+                edits.insert(i, Edit::Remove);
+            }
+            _ => (),
+        }
+    }
+
+    edits
+}
+
+/// Checks if `label` is defined right after the instruction at `i`, with nothing in between but other labels
+fn jumps_to_next(leaves: &[&InstructionNode], i: usize, label: &str) -> bool {
+    leaves[i + 1..].iter()
+        .map_while(|leaf| match leaf {
+            InstructionNode::DEF(name, _) => Some(name),
+            _ => None,
+        })
+        .any(|name| name == label)
+}
+
+/// Checks if the LDA at `i` loads the same address as an earlier one, with nothing in between that changes H or L
+fn loads_same_address(leaves: &[&InstructionNode], i: usize) -> bool {
+    let InstructionNode::LDA(address) = leaves[i] else {
+        return false;
+    };
+
+    for leaf in leaves[..i].iter().rev() {
+        match leaf {
+            InstructionNode::LDA(previous) => return previous.to_string() == address.to_string(),
+
+            InstructionNode::LW(register, _) | InstructionNode::MW(register, _) | InstructionNode::POP(register) |
+                InstructionNode::ADD(register, _) | InstructionNode::SUB(register, _) => {
+                if matches!(register.0, Register::H | Register::L) {
+                    return false;
+                }
+            }
+
+            // jumps that aren't taken leave H and L alone
+            InstructionNode::JZ(_, _) | InstructionNode::JC(_) => (),
+            InstructionNode::SW(_, _) | InstructionNode::PUSH(_) | InstructionNode::OUT(_) | InstructionNode::NOP => (),
+
+            // anything could happen before reaching a label, or after a call returns
+            InstructionNode::DEF(..) | InstructionNode::CALL(_) | InstructionNode::RET |
+                InstructionNode::JMP(_) | InstructionNode::HLT | InstructionNode::Macro(_) => return false,
+        }
+    }
+
+    false
+}
+
+/// Checks if the carry set by the instruction at `i` is replaced or the program halts before anything can read it
+fn carry_unused(leaves: &[&InstructionNode], i: usize) -> bool {
+    for leaf in &leaves[i + 1..] {
+        match leaf {
+            InstructionNode::ADD(_, _) | InstructionNode::SUB(_, _) | InstructionNode::HLT => return true,
+
+            // code jumped to could read it
+            InstructionNode::JC(_) | InstructionNode::JZ(_, _) | InstructionNode::JMP(_) |
+                InstructionNode::CALL(_) | InstructionNode::RET | InstructionNode::Macro(_) => return false,
+
+            InstructionNode::LW(_, _) | InstructionNode::SW(_, _) | InstructionNode::MW(_, _) | InstructionNode::PUSH(_) |
+                InstructionNode::POP(_) | InstructionNode::LDA(_) | InstructionNode::OUT(_) | InstructionNode::NOP | InstructionNode::DEF(..) => (),
+        }
+    }
+
+    // the next subroutine could read it
+    false
+}
@@ -1,10 +1,14 @@
-use crate::compiling::{compiler::Compiler, error_handler::CompilerError, lexer::TokenType};
+use core::fmt;
+use std::collections::HashMap;
 
-use super::{number_nodes::{Imm16, Imm8}, Node, Parser};
+use crate::compiling::{error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}};
+
+use super::{number_nodes::Imm16, Parser};
 
 #[derive(Debug)]
 pub struct PlaceholderNode {
-    name: String
+    pub name: String,
+    token: Token
 }
 
 impl PlaceholderNode {
@@ -12,82 +16,72 @@ impl PlaceholderNode {
         let identifier = parser.advance();
         if let TokenType::Identifier(str) = &identifier.token_type {
             Ok(PlaceholderNode {
-                name: String::from(str)
+                name: String::from(str),
+                token: identifier.clone()
             })
         } else {
             Err(CompilerError::expected("Identifier", identifier, false))
         }
     }
 
-    pub fn compile(&self, compiler: &mut Compiler, imm8: bool) {
-        if compiler.scope.contains_key(&self.name) {
-            let value = compiler.scope.get(&self.name).unwrap().clone();
+    /// Returns an error if this placeholder is not in scope, suggesting a similar name if there is one
+    pub fn check(&self, scope: &HashMap<String, Imm16>) -> Result<(), CompilerError> {
+        if scope.contains_key(&self.name) {
+            return Ok(());
+        }
 
-            if imm8 {
-                Imm8::from_imm16(value).compile(compiler);
-            } else {
-                value.compile(compiler);
-            }
-        } else {
-            panic!("Placeholder {} does not exist", self.name);
+        let error = CompilerError::from_token(ErrorCode::NoSuchPlaceholder(self.name.clone()), &self.token, false);
+        match suggestion(&self.name, scope) {
+            Some(name) => Err(error.with_help(&format!("did you mean {}?", name))),
+            None => Err(error),
         }
     }
-}
 
-#[derive(Debug)]
-pub enum PlaceholderOrImm16Node {
-    PlaceholderNode(PlaceholderNode),
-    Imm16(Imm16)
+    pub fn evaluate(&self, scope: &HashMap<String, Imm16>) -> Result<Imm16, CompilerError> {
+        self.check(scope)?;
+        Ok(scope[&self.name].clone())
+    }
 }
 
-impl Node for PlaceholderOrImm16Node {
-    fn populate(parser: &mut Parser) -> Result<PlaceholderOrImm16Node, CompilerError> {
-        match parser.peek().token_type {
-            TokenType::Identifier(_) => Ok(PlaceholderOrImm16Node::PlaceholderNode(PlaceholderNode::populate(parser)?)),
-            TokenType::Number(_) => Ok(PlaceholderOrImm16Node::Imm16(Imm16::populate(parser)?)),
-            _ => {
-                Err(CompilerError::expected("Identifier or number", parser.peek(), false))
-            }
-        }
-    }
+/// Finds the name in scope closest to name, if any are close enough to be a typo
+fn suggestion(name: &str, scope: &HashMap<String, Imm16>) -> Option<String> {
+    let limit = (name.len() / 3).max(1);
 
-    fn get_size(&self) -> i32 {
-        return 2;
-    }
+    scope.keys()
+        .map(|candidate| (display_name(candidate), candidate))
+        .map(|(shown, candidate)| (edit_distance(&name.to_lowercase(), &shown.to_lowercase()), shown, candidate))
+        .filter(|(distance, _, _)| *distance <= limit)
+        .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(b.2)))
+        .map(|(_, shown, _)| shown.to_string())
+}
 
-    fn compile(&self, compiler: &mut Compiler) {
-        match self {
-            PlaceholderOrImm16Node::PlaceholderNode(node) => node.compile(compiler, false),
-            PlaceholderOrImm16Node::Imm16(node) => node.compile(compiler)
-        }
+/// Labels inside macros are renamed to macro#N.label, only the label part is useful to show
+fn display_name(name: &str) -> &str {
+    match name.split_once('#').and_then(|(_, rest)| rest.split_once('.')) {
+        Some((_, label)) => label,
+        None => name,
     }
 }
 
-#[derive(Debug)]
-pub enum PlaceholderOrImm8Node {
-    PlaceholderNode(PlaceholderNode),
-    Imm8(Imm8)
-}
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
 
-impl Node for PlaceholderOrImm8Node {
-    fn populate(parser: &mut Parser) -> Result<PlaceholderOrImm8Node, CompilerError> {
-        match parser.peek().token_type {
-            TokenType::Identifier(_) => Ok(PlaceholderOrImm8Node::PlaceholderNode(PlaceholderNode::populate(parser)?)),
-            TokenType::Number(_) => Ok(PlaceholderOrImm8Node::Imm8(Imm8::populate(parser)?)),
-            _ => {
-                Err(CompilerError::expected("Identifier or number", parser.peek(), false))
-            }
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
         }
+        previous = current;
     }
 
-    fn get_size(&self) -> i32 {
-        return 2;
-    }
+    previous[b.len()]
+}
 
-    fn compile(&self, compiler: &mut Compiler) {
-        match self {
-            PlaceholderOrImm8Node::PlaceholderNode(node) => node.compile(compiler, true),
-            PlaceholderOrImm8Node::Imm8(node) => node.compile(compiler)
-        }
+impl fmt::Display for PlaceholderNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use crate::compiling::{error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}};
+
+use super::{expression_node::ExpressionNode, number_nodes::Imm16, Parser};
+
+/// An @if block that is currently open
+struct Conditional {
+    token: Token,
+    /// true if the block containing this one is being assembled
+    parent: bool,
+    /// true once a branch of this block has been assembled
+    taken: bool,
+    active: bool,
+    has_else: bool
+}
+
+/// Handles includes and conditional assembly before the tokens are parsed.
+/// 
+/// `known` holds the command line defines and every constant seen so far that could be calculated
+pub fn preprocess(parser: &mut Parser, tokens: Vec<Token>, known: &mut HashMap<String, Imm16>) -> Result<Vec<Token>, Vec<CompilerError>> {
+    let mut output: Vec<Token> = Vec::new();
+    let mut errors: Vec<CompilerError> = Vec::new();
+    let mut conditionals: Vec<Conditional> = Vec::new();
+
+    // the end of the file has to survive even if it is inside a skipped block
+    let mut tokens = tokens;
+    let end_of_file = match tokens.last() {
+        Some(t) if matches!(t.token_type, TokenType::EndOfFile) => tokens.pop(),
+        _ => None
+    };
+
+    for line in split_lines(tokens) {
+        let active = conditionals.last().map_or(true, |c| c.active);
+
+        // only the nesting of skipped lines is checked, so anything the lexer found wrong with them doesn't matter
+        if !active {
+            if let Some(token) = line.first() {
+                parser.skipped.insert((token.file.clone(), token.line));
+            }
+        }
+
+        // skip indentation
+        let start = line.iter().position(|t| !matches!(t.token_type, TokenType::Indent(_))).unwrap_or(line.len());
+        let rest = &line[start..];
+
+        let Some(first) = rest.first() else {
+            if active {
+                output.extend(line);
+            }
+            continue;
+        };
+
+        match &first.token_type {
+            TokenType::If | TokenType::IfDef | TokenType::IfNDef => {
+                let condition = if active {
+                    match evaluate_condition(rest, known) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            errors.push(e);
+                            false
+                        }
+                    }
+                } else {
+                    false
+                };
+
+                conditionals.push(Conditional {
+                    token: first.clone(),
+                    parent: active,
+                    taken: condition,
+                    active: active && condition,
+                    has_else: false
+                });
+            }
+            TokenType::Else => {
+                match conditionals.last_mut() {
+                    Some(c) if !c.has_else => {
+                        c.active = c.parent && !c.taken;
+                        c.taken = true;
+                        c.has_else = true;
+                    }
+                    _ => errors.push(CompilerError::from_token(ErrorCode::UnmatchedConditional(first.token_type.clone()), first, true)),
+                }
+            }
+            TokenType::EndIf => {
+                if conditionals.pop().is_none() {
+                    errors.push(CompilerError::from_token(ErrorCode::UnmatchedConditional(first.token_type.clone()), first, true));
+                }
+            }
+
+            // everything else in a skipped block is ignored
+            _ if !active => (),
+
+            TokenType::Include => {
+                let path = match rest.get(1).map(|t| &t.token_type) {
+                    Some(TokenType::Identifier(path)) => path.clone(),
+                    _ => {
+                        errors.push(CompilerError::expected("valid path", rest.get(1).unwrap_or(first), true));
+                        continue;
+                    }
+                };
+
+                match parser.add_file(&path, &rest[1]) {
+                    Ok(Some(tokens)) => {
+                        match preprocess(parser, tokens, known) {
+                            Ok(mut tokens) => output.append(&mut tokens),
+                            Err(mut e) => errors.append(&mut e),
+                        }
+                        output.push(Token::new(TokenType::NewLine, first.line, first.file.clone()));
+                    }
+                    Ok(None) => (), // already included
+                    Err(e) => errors.push(e),
+                }
+            }
+            TokenType::Constant => {
+                remember_constant(rest, known);
+                output.extend(line);
+            }
+            _ => output.extend(line),
+        }
+    }
+
+    if let Some(c) = conditionals.first() {
+        errors.push(CompilerError::from_token(ErrorCode::MissingEndIf, &c.token, true));
+    }
+
+    output.extend(end_of_file);
+
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Splits tokens into lines, each ending in a new line token
+fn split_lines(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut lines: Vec<Vec<Token>> = Vec::new();
+    let mut line: Vec<Token> = Vec::new();
+
+    for token in tokens {
+        let end = matches!(token.token_type, TokenType::NewLine);
+        line.push(token);
+        if end {
+            lines.push(line);
+            line = Vec::new();
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Returns the tokens after the first one up to the end of the line, followed by an end of file token
+fn arguments(line: &[Token]) -> Vec<Token> {
+    let mut tokens: Vec<Token> = line[1..].iter()
+        .take_while(|t| !matches!(t.token_type, TokenType::NewLine | TokenType::EndOfFile))
+        .cloned()
+        .collect();
+    tokens.push(Token::new(TokenType::EndOfFile, line[0].line, line[0].file.clone()));
+    tokens
+}
+
+fn evaluate_condition(line: &[Token], known: &HashMap<String, Imm16>) -> Result<bool, CompilerError> {
+    let directive = &line[0];
+    let tokens = arguments(line);
+
+    match directive.token_type {
+        TokenType::IfDef | TokenType::IfNDef => {
+            let defined = match &tokens[0].token_type {
+                TokenType::Identifier(name) => known.contains_key(name),
+                _ => return Err(CompilerError::expected("Identifier", &tokens[0], true)),
+            };
+            if !matches!(tokens[1].token_type, TokenType::EndOfFile) {
+                return Err(CompilerError::expected("New Line", &tokens[1], true));
+            }
+
+            Ok(defined == matches!(directive.token_type, TokenType::IfDef))
+        }
+        _ => {
+            let mut parser = Parser::from_tokens(tokens, directive.file.clone());
+            let expression = ExpressionNode::populate(&mut parser)?;
+
+            if !matches!(parser.peek().token_type, TokenType::EndOfFile) {
+                return Err(CompilerError::expected("New Line", parser.peek(), true));
+            }
+
+            if let Some(name) = expression.names().into_iter().find(|name| !known.contains_key(*name)) {
+                return Err(CompilerError::from_token(ErrorCode::NoSuchPlaceholder(name.clone()), directive, true));
+            }
+
+            Ok(expression.evaluate(known)? != 0)
+        }
+    }
+}
+
+/// Remembers the value of a constant so later conditions can use it.
+/// Constants that can't be calculated yet, such as ones using subroutines, are left for the compiler.
+fn remember_constant(line: &[Token], known: &mut HashMap<String, Imm16>) {
+    let tokens = arguments(line);
+
+    let name = match &tokens[0].token_type {
+        TokenType::Identifier(name) => name.clone(),
+        _ => return,
+    };
+
+    let mut parser = Parser::from_tokens(tokens[1..].to_vec(), line[0].file.clone());
+    let Ok(expression) = ExpressionNode::populate(&mut parser) else {
+        return;
+    };
+
+    if expression.names().iter().all(|name| known.contains_key(*name)) {
+        if let Some(value) = expression.evaluate(known).ok().and_then(Imm16::new) {
+            known.insert(name, value);
+        }
+    }
+}
@@ -1,13 +1,26 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap, ops::RangeInclusive};
 
-use crate::compiling::{error_handler::{self, CompilerError, ErrorCode}, lexer::{Token, TokenType}};
+use crate::compiling::{error_handler::{self, CompilerError, ErrorCode}, lexer::{Token, TokenType}, symbols::{Allocation, Symbol, SymbolKind}};
 
-use super::{macros::Macro, number_nodes::Imm16, subroutine_node::SubroutineNode, Node, Parser};
+use super::{expression_node::ExpressionNode, macros::Macro, optimizer, number_nodes::Imm16, subroutine_node::SubroutineNode, variable_node::{self, ReserveNode, VariableNode}, Parser};
 
 #[derive(Debug)]
 pub struct ProgramNode {
     subroutines: Vec<SubroutineNode>,
+    constants: Vec<ConstantNode>,
+    variables: Vec<VariableNode>,
+    reserved: Vec<ReserveNode>,
     placeholders: HashMap<String, Imm16>,
+    pub symbols: Vec<Symbol>,
+    /// the RAM given to each variable once placeholders are calculated
+    pub allocations: Vec<Allocation>,
+}
+
+#[derive(Debug)]
+struct ConstantNode {
+    name: String,
+    token: Token,
+    value: ExpressionNode
 }
 
 impl ProgramNode {
@@ -15,7 +28,9 @@ impl ProgramNode {
         let mut subroutines: Vec<SubroutineNode> = Vec::new();
         let mut macros: HashMap<String, Macro> = HashMap::new();
         let mut errors: Vec<CompilerError> = Vec::new();
-        let mut placeholders: HashMap<String, Imm16> = HashMap::new();
+        let mut constants: Vec<ConstantNode> = Vec::new();
+        let mut variables: Vec<VariableNode> = Vec::new();
+        let mut reserved: Vec<ReserveNode> = Vec::new();
 
         let mut main = false;
 
@@ -29,17 +44,26 @@ impl ProgramNode {
                 TokenType::Macro => {
                     match Macro::populate(parser) {
                         Ok(m) => {
-                            macros.insert(m.name.clone(), m);
+                            if let Some(existing) = macros.get(&m.name) {
+                                errors.push(CompilerError::from_token(ErrorCode::DuplicateSymbol(m.name.clone()), &m.token, false)
+                                    .with_note("first defined here", &existing.token));
+                            } else {
+                                macros.insert(m.name.clone(), m);
+                            }
                         },
                         Err(e) => return Err(vec![e]),
                     }
                 }
-                TokenType::Identifier(_) => {
+                TokenType::Identifier(ref name) => {
+                    // a main subroutine with errors in it still counts
+                    if name == "main" && file == parser.entry {
+                        main = true;
+                    }
+
                     let sub = SubroutineNode::populate(parser);
                     match sub {
                         Ok(sub) => {
-                            if sub.name == "main" && file == "main.bread" {
-                                main = true;
+                            if sub.name == "main" && file == parser.entry {
                                 subroutines.insert(0, sub)
                             } else {
                                 subroutines.push(sub)
@@ -55,36 +79,15 @@ impl ProgramNode {
                         }
                     }
                 },
-                TokenType::Include => {
-                    parser.advance(); // advance past include
-
-                    let mut path: String = String::from("");
-
-                    while let TokenType::Identifier(p) = &parser.advance().token_type {
-                        path += p;
-                        path += " ";
-                    }
-
-                    if path.is_empty() {
-                        errors.push(
-                            CompilerError::expected("valid path", parser.current(), true)
-                        );
-                        return Err(errors);
-                    }
-
-                    if let Err(e) = parser.add_file(&String::from(path.trim())) {
-                        errors.push(e);
-                        return Err(errors);
-                    }
-                },
                 TokenType::Constant => {
                     parser.advance(); // advance past constant
-                    let name = if let TokenType::Identifier(name) = &parser.advance().token_type {
+                    let token: Token = parser.advance().clone();
+                    let name = if let TokenType::Identifier(name) = &token.token_type {
                         name.clone()
                     } else {
                         return Err(errors)
                     };
-                    let value = match Imm16::populate(parser) {
+                    let value = match ExpressionNode::populate(parser) {
                         Ok(v) => v,
                         Err(e) => {
                             errors.push(e);
@@ -92,24 +95,45 @@ impl ProgramNode {
                         }
                     };
 
-                    placeholders.insert(name, value);
+                    constants.push(ConstantNode { name, token, value });
+                }
+                TokenType::Variable => {
+                    match VariableNode::populate(parser) {
+                        Ok(variable) => variables.push(variable),
+                        Err(e) => {
+                            errors.push(e);
+                            return Err(errors);
+                        }
+                    }
+                }
+                TokenType::Reserve => {
+                    match ReserveNode::populate(parser) {
+                        Ok(reserve) => reserved.push(reserve),
+                        Err(e) => {
+                            errors.push(e);
+                            return Err(errors);
+                        }
+                    }
                 }
                 _ => {
                     errors.push(
-                        CompilerError::expected("macro, subroutine, include, or end of file", parser.peek(), false));
+                        CompilerError::expected("macro, subroutine, constant, variable, or end of file", parser.peek(), false));
                     parser.advance();
                 }
             }
         }
 
         if !main {
-            errors.push(CompilerError::new(ErrorCode::NoMainSubroutine, &"main.bread".to_string(), 0, true));
+            errors.push(CompilerError::new(ErrorCode::NoMainSubroutine, &parser.entry, 0, true));
             return Err(errors);
         }
 
+        errors.append(&mut find_duplicates(&parser.tokens, &subroutines, &constants, &variables, &parser.defines));
+
         // populate macros
+        let expanded = Cell::new(0);
         for sub in &mut subroutines {
-            if let Err(mut e) = sub.populate_macros(&macros) {
+            if let Err(mut e) = sub.populate_macros(&macros, &expanded) {
                 if error_handler::has_critical(&e) {
                     errors.append(&mut e);
                     return Err(errors);
@@ -123,36 +147,227 @@ impl ProgramNode {
             return Err(errors);
         }
 
+        if parser.optimize {
+            match optimizer::unlabelled_jump(&subroutines) {
+                Some(token) => parser.warnings.push(CompilerError::warning(ErrorCode::NotOptimized, token)),
+                None => {
+                    for sub in &mut subroutines {
+                        sub.optimize();
+                    }
+                }
+            }
+        }
+
+        // command line defines act like constants
+        let mut symbols: Vec<Symbol> = Vec::new();
+        for (name, value) in &parser.defines {
+            symbols.push(Symbol {
+                name: name.clone(),
+                value: value.value(),
+                kind: SymbolKind::Constant,
+                file: String::from("<command line>"),
+                line: 0
+            });
+        }
+
         let mut node = ProgramNode {
             subroutines,
-            placeholders,
+            constants,
+            variables,
+            reserved,
+            placeholders: parser.defines.clone(),
+            symbols,
+            allocations: Vec::new(),
         };
 
-        node.calculate_placeholders();
+        node.calculate_placeholders(parser.rom_size, &parser.ram)?;
+        node.check_placeholders()?;
+        node.resolve()?;
 
         Ok(node)
     }
         
     pub fn compile(&self, compiler: &mut crate::compiling::compiler::Compiler) {
-        compiler.scope = self.placeholders.clone();
-
         for sub in &self.subroutines {
             sub.compile(compiler);
         }
     }
 }
 
+/// Subroutines, constants and variables share one namespace, and labels can't reuse any of those names.
+/// `tokens` are the parsed tokens, the one that comes first in them is the first definition
+fn find_duplicates(tokens: &[Token], subroutines: &[SubroutineNode], constants: &[ConstantNode], variables: &[VariableNode], defines: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+    let mut errors: Vec<CompilerError> = Vec::new();
+    let mut globals: HashMap<&String, &Token> = HashMap::new();
+
+    // main is moved to the front of the subroutines, so go by where each one is in the source
+    let order: HashMap<(&String, usize), usize> = tokens.iter().enumerate()
+        .map(|(i, token)| ((&token.file, token.start), i))
+        .collect();
+
+    let mut names: Vec<(&String, &Token)> = subroutines.iter().map(|s| (&s.name, &s.token))
+        .chain(constants.iter().map(|c| (&c.name, &c.token)))
+        .chain(variables.iter().map(|v| (&v.name, &v.token)))
+        .collect();
+    names.sort_by_key(|(_, token)| order.get(&(&token.file, token.start)).copied().unwrap_or(usize::MAX));
+
+    for (name, token) in names {
+        if let Some(first) = globals.get(name) {
+            errors.push(CompilerError::from_token(ErrorCode::DuplicateSymbol(name.clone()), token, false)
+                .with_note("first defined here", first));
+            continue;
+        }
+
+        if defines.contains_key(name) {
+            errors.push(CompilerError::from_token(ErrorCode::DuplicateSymbol(name.clone()), token, false)
+                .with_help(&format!("{} is also defined on the command line", name)));
+        }
+
+        globals.insert(name, token);
+    }
+
+    for subroutine in subroutines {
+        let mut labels: HashMap<&String, &Token> = HashMap::new();
+
+        for (name, token) in subroutine.labels() {
+            if let Some(first) = labels.get(name).or(globals.get(name)) {
+                errors.push(CompilerError::from_token(ErrorCode::DuplicateSymbol(name.clone()), token, false)
+                    .with_note("first defined here", first));
+                continue;
+            }
+
+            if defines.contains_key(name) {
+                errors.push(CompilerError::from_token(ErrorCode::DuplicateSymbol(name.clone()), token, false)
+                    .with_help(&format!("{} is also defined on the command line", name)));
+            }
+
+            labels.insert(name, token);
+        }
+    }
+
+    errors
+}
+
 impl ProgramNode {
-    pub fn calculate_placeholders(&mut self) {
-        let mut position = 0;
+    pub fn calculate_placeholders(&mut self, rom_size: usize, ram: &RangeInclusive<u16>) -> Result<(), Vec<CompilerError>> {
+        self.check_size(rom_size).map_err(|e| vec![e])?;
+
+        let mut position: u16 = 0;
+        let mut local_labels: Vec<(String, Imm16)> = Vec::new();
         for subroutine in &self.subroutines {
             self.placeholders.insert(subroutine.name.clone(), Imm16::from(position));
-            position += subroutine.get_size() as u16;
+            self.symbols.push(Symbol::new(&subroutine.name, position, SymbolKind::Subroutine, &subroutine.token));
+            local_labels.append(&mut subroutine.local_labels(position));
+            // a program that fills the whole address space ends at 0x10000
+            position = position.wrapping_add(subroutine.get_size() as u16);
         }
         position = 0;
 
+        // labels like main.loop can be used anywhere
+        self.placeholders.extend(local_labels);
+
+        self.allocations = variable_node::allocate(&self.variables, &self.reserved, ram)?;
+        for (variable, allocation) in self.variables.iter().zip(&self.allocations) {
+            self.placeholders.insert(variable.name.clone(), Imm16::from(allocation.address));
+            self.symbols.push(Symbol::new(&variable.name, allocation.address, SymbolKind::Variable, &variable.token));
+        }
+
+        // constants can use subroutines, variables and each other so they go next
+        self.calculate_constants()?;
+
         for subroutine in &mut self.subroutines {
-            subroutine.calculate_placeholders(&mut position, &self.placeholders);
+            subroutine.calculate_placeholders(&mut position, &self.placeholders, &mut self.symbols);
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure every subroutine fits in the ROM before any addresses are calculated
+    fn check_size(&self, rom_size: usize) -> Result<(), CompilerError> {
+        let total: usize = self.subroutines.iter().map(|s| s.get_size() as usize).sum();
+        let mut end = 0;
+
+        for subroutine in &self.subroutines {
+            end += subroutine.get_size() as usize;
+
+            if end > rom_size {
+                return Err(CompilerError::from_token(ErrorCode::RomOverflow(subroutine.name.clone(), end, rom_size), &subroutine.token, true)
+                    .with_help(&format!("the whole program is {} bytes", total)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn calculate_constants(&mut self) -> Result<(), Vec<CompilerError>> {
+        let mut errors: Vec<CompilerError> = Vec::new();
+        let mut pending: Vec<ConstantNode> = std::mem::take(&mut self.constants);
+
+        while !pending.is_empty() {
+            let waiting: Vec<String> = pending.iter().map(|c| c.name.clone()).collect();
+
+            // a constant is ready once nothing it uses is still waiting
+            let (ready, blocked): (Vec<ConstantNode>, Vec<ConstantNode>) = pending.into_iter()
+                .partition(|c| c.value.names().iter().all(|name| !waiting.contains(name)));
+
+            if ready.is_empty() {
+                for constant in &blocked {
+                    errors.push(CompilerError::from_token(ErrorCode::CircularConstant(constant.name.clone()), &constant.token, false));
+                }
+                break;
+            }
+
+            for constant in &ready {
+                let value = constant.value.evaluate(&self.placeholders).and_then(|num| {
+                    Imm16::new(num).ok_or(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, Imm16::MIN, Imm16::MAX), &constant.token, false))
+                });
+
+                match value {
+                    Ok(value) => {
+                        self.symbols.push(Symbol::new(&constant.name, value.value(), SymbolKind::Constant, &constant.token));
+                        self.placeholders.insert(constant.name.clone(), value);
+                    }
+                    Err(e) => errors.push(e),
+                }
+            }
+
+            pending = blocked;
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Makes sure every placeholder used by an instruction exists once the placeholders are calculated, before any expression is resolved
+    pub fn check_placeholders(&self) -> Result<(), Vec<CompilerError>> {
+        let errors: Vec<CompilerError> = self.subroutines.iter()
+            .flat_map(|subroutine| subroutine.check_placeholders())
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Calculates every expression once all placeholders are known
+    pub fn resolve(&mut self) -> Result<(), Vec<CompilerError>> {
+        let mut errors: Vec<CompilerError> = Vec::new();
+
+        for subroutine in &mut self.subroutines {
+            if let Err(mut e) = subroutine.resolve() {
+                errors.append(&mut e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 }
\ No newline at end of file
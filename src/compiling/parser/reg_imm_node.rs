@@ -1,10 +1,14 @@
+use core::fmt;
+
+use std::collections::HashMap;
+
 use crate::compiling::{compiler::Compiler, error_handler::CompilerError, lexer::TokenType};
 
-use super::{placeholder_node::PlaceholderOrImm8Node, register_node::RegisterNode, Node, Parser};
+use super::{expression_node::Imm8Expression, number_nodes::Imm16, register_node::RegisterNode, Node, Parser};
 
 #[derive(Debug)]
 pub enum RegOrImmNode {
-    Immediate(PlaceholderOrImm8Node),
+    Immediate(Imm8Expression),
     Register(RegisterNode)
 }
 
@@ -15,8 +19,8 @@ impl Node for RegOrImmNode {
             TokenType::Register(_) => {
                 Ok(RegOrImmNode::Register(RegisterNode::populate(parser)?))
             },
-            TokenType::Number(_) | TokenType::Identifier(_) => {
-                Ok(RegOrImmNode::Immediate(PlaceholderOrImm8Node::populate(parser)?))
+            TokenType::Number(_) | TokenType::Identifier(_) | TokenType::OpenParenthesis | TokenType::Minus => {
+                Ok(RegOrImmNode::Immediate(Imm8Expression::populate(parser)?))
             },
             _ => {
                 Err(CompilerError::expected("Register or imm8", token, false))
@@ -31,4 +35,28 @@ impl Node for RegOrImmNode {
     fn compile(&self, _compiler: &mut Compiler) {
         panic!("Compiling a RegOrImmNode is not a valid operation");
     }
-}
\ No newline at end of file
+}
+impl RegOrImmNode {
+    pub fn check_placeholders(&self, scope: &HashMap<String, Imm16>) -> Vec<CompilerError> {
+        match self {
+            RegOrImmNode::Immediate(imm) => imm.check_placeholders(scope),
+            RegOrImmNode::Register(_) => Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, scope: &HashMap<String, Imm16>) -> Result<(), CompilerError> {
+        match self {
+            RegOrImmNode::Immediate(imm) => imm.resolve(scope),
+            RegOrImmNode::Register(_) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for RegOrImmNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegOrImmNode::Immediate(imm) => write!(f, "{}", imm),
+            RegOrImmNode::Register(reg) => write!(f, "{}", reg),
+        }
+    }
+}
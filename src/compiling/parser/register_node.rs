@@ -1,3 +1,5 @@
+use core::fmt;
+
 use crate::compiling::{compiler::Compiler, error_handler::{CompilerError, ErrorCode}, lexer::TokenType, Register};
 
 use super::{Node, Parser};
@@ -26,4 +28,9 @@ impl Node for RegisterNode {
     fn compile(&self, _compiler: &mut Compiler) {
         panic!("Compiling a RegisterNode is not a valid operation")
     }
-}
\ No newline at end of file
+}
+impl fmt::Display for RegisterNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
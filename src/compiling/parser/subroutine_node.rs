@@ -1,15 +1,16 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap};
 
 use colored::Colorize;
 
-use crate::compiling::{error_handler::{self, CompilerError, ErrorCode}, lexer::TokenType};
+use crate::compiling::{compiler::ListingEntry, error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, symbols::{Symbol, SymbolKind}};
 
-use super::{instruction_node::InstructionNode, macros::{Macro, MacroHolder, MacroNode}, number_nodes::Imm16, Node, Parser};
+use super::{instruction_node::{compile_instructions, InstructionNode}, macros::{self, Macro, MacroHolder}, number_nodes::Imm16, optimizer, Node, Parser};
 
 #[derive(Debug)]
 pub struct SubroutineNode {
     pub name: String,
-    instructions: Vec<InstructionNode>,
+    pub token: Token,
+    instructions: Vec<(Token, InstructionNode)>,
     placeholders: HashMap<String, Imm16>
 }
 
@@ -18,12 +19,12 @@ impl SubroutineNode {
         parser.skip_new_lines();
 
         // identifier
-        let identifier = parser.advance();
+        let token = parser.advance().clone();
         let name: String;
-        if let TokenType::Identifier(n) = &identifier.token_type {
+        if let TokenType::Identifier(n) = &token.token_type {
             name = n.clone();
         } else {
-            return Err(vec![CompilerError::expected("Identifier", identifier, true)]);
+            return Err(vec![CompilerError::expected("Identifier", &token, true)]);
         }
 
         // expect colon
@@ -36,18 +37,18 @@ impl SubroutineNode {
             return Err(vec![CompilerError::expected("New Line", parser.current(), true)]);
         }
 
+        parser.qualify_local_labels(&name);
         let instructions = get_instructions(parser)?;
 
-        if !matches!(instructions.last(), Some(InstructionNode::HLT) | Some(InstructionNode::JMP(_)) | None) {
-            error_handler::print_warning(&format!("Subroutine {} does not end in HLT or JMP", name));
-        }
-
-        if matches!(instructions.last(), None) {
-            error_handler::print_warning(&format!("Subroutine {} does not contain any instructions", name));
+        match instructions.last() {
+            Some((_, InstructionNode::HLT)) | Some((_, InstructionNode::JMP(_))) | Some((_, InstructionNode::RET)) => (),
+            Some((last, _)) => parser.warnings.push(CompilerError::warning(ErrorCode::NoReturn(name.clone()), last)),
+            None => parser.warnings.push(CompilerError::warning(ErrorCode::EmptySubroutine(name.clone()), &token)),
         }
 
         Ok(SubroutineNode {
             name,
+            token,
             instructions,
             placeholders: HashMap::new()
         })
@@ -56,7 +57,7 @@ impl SubroutineNode {
     pub fn get_size(&self) -> i32 {
         let mut size = 0;
         
-        for node in &self.instructions {
+        for (_, node) in &self.instructions {
             size += node.get_size();
         }
 
@@ -64,63 +65,105 @@ impl SubroutineNode {
     }
     
     pub fn compile(&self, compiler: &mut crate::compiling::compiler::Compiler) {
-        compiler.scope = self.placeholders.clone();
-        for instructions in &self.instructions {
-            instructions.compile(compiler);
-        }
+        compiler.listing.push(ListingEntry::Subroutine(self.name.clone(), compiler.bytes.len() as u16));
+        compile_instructions(&self.instructions, compiler);
     }
 }
 
 impl SubroutineNode {
-    pub fn calculate_placeholders(&mut self, position: &mut u16, placeholders: &HashMap<String, Imm16>) {
+    pub fn calculate_placeholders(&mut self, position: &mut u16, placeholders: &HashMap<String, Imm16>, symbols: &mut Vec<Symbol>) {
         let initial_position = *position;
 
         self.placeholders = placeholders.clone();
-        for instruction in &self.instructions {
-            if let InstructionNode::DEF(name) = instruction {
+        for (token, instruction) in &self.instructions {
+            if let InstructionNode::DEF(name, _) = instruction {
+                symbols.push(Symbol::new(name, *position, SymbolKind::Label, token));
                 self.placeholders.insert(name.clone(), Imm16::from(*position));
             } else {
-                *position += instruction.get_size() as u16;
+                *position = position.wrapping_add(instruction.get_size() as u16);
             }
         }
 
         // ok now macros
         *position = initial_position;
 
-        for instruction in &mut self.instructions {
+        for (_, instruction) in &mut self.instructions {
             if let InstructionNode::Macro(holder) = instruction {
                 if let MacroHolder::Macro(node) = holder {
-                    node.calculate_placeholders(position, &self.placeholders)
+                    node.calculate_placeholders(position, &self.placeholders, symbols)
                 } else {
                     panic!("All macros should be populated by now");
                 }
             } else {
-                *position += instruction.get_size() as u16;
+                *position = position.wrapping_add(instruction.get_size() as u16);
             }
         }
     }
 
-    pub fn populate_macros(&mut self, macros: &HashMap<String, Macro>) -> Result<(), Vec<CompilerError>> {
-        for instruction in &mut self.instructions {
-            if let InstructionNode::Macro(holder) = instruction {
-                if let MacroHolder::Placeholder(name, args, token) = holder {
-                    let m = macros.get(name);
-                    
-                    if let Some(m) = m {
-                        *instruction = InstructionNode::Macro(MacroHolder::Macro(MacroNode::populate(m, args)?));
-                    } else {
-                        return Err(vec![CompilerError::from_token(ErrorCode::NoSuchMacro(name.clone()), token, false)]);
-                    }
-                }
+    pub fn check_placeholders(&self) -> Vec<CompilerError> {
+        self.instructions.iter()
+            .flat_map(|(_, instruction)| instruction.check_placeholders(&self.placeholders))
+            .collect()
+    }
+
+    pub fn resolve(&mut self) -> Result<(), Vec<CompilerError>> {
+        let mut errors: Vec<CompilerError> = Vec::new();
+
+        for (_, instruction) in &mut self.instructions {
+            if let Err(mut e) = instruction.resolve(&self.placeholders) {
+                errors.append(&mut e);
             }
         }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the labels defined directly in this subroutine, not inside macros
+    pub fn labels(&self) -> Vec<(&String, &Token)> {
+        self.instructions.iter().filter_map(|(_, instruction)| match instruction {
+            InstructionNode::DEF(name, token) => Some((name, token)),
+            _ => None
+        }).collect()
+    }
+
+    /// Returns the addresses of the labels that start with a `.`, which can be used from other subroutines as `name.label`.
+    /// `start` is the address of the subroutine
+    pub fn local_labels(&self, start: u16) -> Vec<(String, Imm16)> {
+        let prefix = format!("{}.", self.name);
+        let mut position = start;
+        let mut labels: Vec<(String, Imm16)> = Vec::new();
+
+        for (_, instruction) in &self.instructions {
+            match instruction {
+                InstructionNode::DEF(name, _) if name.starts_with(&prefix) => labels.push((name.clone(), Imm16::from(position))),
+                _ => position = position.wrapping_add(instruction.get_size() as u16),
+            }
+        }
+
+        labels
+    }
+
+    /// Expands every macro used by the subroutine. `expanded` counts the instructions expanded in the whole program
+    pub fn populate_macros(&mut self, macros: &HashMap<String, Macro>, expanded: &Cell<usize>) -> Result<(), Vec<CompilerError>> {
+        macros::populate_macros(&mut self.instructions, macros, &[], expanded)
+    }
+
+    pub fn instructions(&self) -> &[(Token, InstructionNode)] {
+        &self.instructions
+    }
+
+    /// Removes redundant instructions. Macros have to be populated first
+    pub fn optimize(&mut self) {
+        optimizer::optimize(&mut self.instructions);
     }
 }
 
-pub fn get_instructions(parser: &mut Parser) -> Result<Vec<InstructionNode>, Vec<CompilerError>> {
-    let mut instructions: Vec<InstructionNode> = Vec::new();
+pub fn get_instructions(parser: &mut Parser) -> Result<Vec<(Token, InstructionNode)>, Vec<CompilerError>> {
+    let mut instructions: Vec<(Token, InstructionNode)> = Vec::new();
     let mut errors: Vec<CompilerError> = Vec::new();
 
     'parser: while !parser.is_at_end() {
@@ -137,9 +180,10 @@ pub fn get_instructions(parser: &mut Parser) -> Result<Vec<InstructionNode>, Vec
                 break 'lines;
             }
         }
+        let token = parser.peek().clone();
         match InstructionNode::populate(parser) {
             Ok(i) => { 
-                instructions.push(i);
+                instructions.push((token, i));
 
                 while !matches!(parser.peek().token_type, TokenType::NewLine | TokenType::EndOfFile) {
                     errors.push(CompilerError::expected("New Line", parser.advance(), false))
@@ -0,0 +1,110 @@
+use std::ops::RangeInclusive;
+
+use crate::compiling::{error_handler::{CompilerError, ErrorCode}, lexer::{Token, TokenType}, symbols::Allocation, ADDRESS_SPACE};
+
+use super::{number_nodes::get_number, Parser};
+
+/// `@var name [size]`, bytes of RAM that are given an address when the program is compiled
+#[derive(Debug)]
+pub struct VariableNode {
+    pub name: String,
+    pub token: Token,
+    pub size: u16,
+}
+
+/// `@reserve address [size]`, RAM that variables are never put in, such as addresses used by hand
+#[derive(Debug)]
+pub struct ReserveNode {
+    address: u16,
+    size: u16,
+}
+
+impl VariableNode {
+    pub fn populate(parser: &mut Parser) -> Result<VariableNode, CompilerError> {
+        parser.advance(); // advance past @var
+
+        let token = parser.advance().clone();
+        let TokenType::Identifier(name) = &token.token_type else {
+            return Err(CompilerError::expected("Identifier", &token, false));
+        };
+
+        let size = get_size(parser, u16::MAX)?;
+
+        Ok(VariableNode { name: name.clone(), token, size })
+    }
+}
+
+impl ReserveNode {
+    pub fn populate(parser: &mut Parser) -> Result<ReserveNode, CompilerError> {
+        parser.advance(); // advance past @reserve
+
+        let num = get_number(parser)?;
+        let address = match u16::try_from(num) {
+            Ok(address) => address,
+            Err(_) => return Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, 0, u16::MAX as i32), parser.current(), false)),
+        };
+
+        // the reserved bytes can't go past the end of RAM
+        let size = get_size(parser, (ADDRESS_SPACE - address as usize).min(u16::MAX as usize) as u16)?;
+
+        Ok(ReserveNode { address, size })
+    }
+}
+
+/// The optional size at the end of the line, which is 1 if it is left out
+fn get_size(parser: &mut Parser, max: u16) -> Result<u16, CompilerError> {
+    let size = match parser.peek().token_type {
+        TokenType::NewLine | TokenType::EndOfFile => 1,
+        _ => {
+            let num = get_number(parser)?;
+            if num < 1 || num > max as i32 {
+                return Err(CompilerError::from_token(ErrorCode::NumberOutOfRange(num, 1, max as i32), parser.current(), false));
+            }
+            num
+        }
+    };
+
+    if !matches!(parser.peek().token_type, TokenType::NewLine | TokenType::EndOfFile) {
+        return Err(CompilerError::expected("New Line", parser.peek(), false));
+    }
+
+    Ok(size as u16)
+}
+
+/// Gives each variable the first addresses in `ram` that are big enough and not used by another variable or reserved.
+/// Variables are placed in the order they were declared
+pub fn allocate(variables: &[VariableNode], reserved: &[ReserveNode], ram: &RangeInclusive<u16>) -> Result<Vec<Allocation>, Vec<CompilerError>> {
+    let end = *ram.end() as usize + 1;
+    let total: usize = variables.iter().map(|v| v.size as usize).sum();
+
+    let mut taken: Vec<(usize, usize)> = reserved.iter()
+        .map(|r| (r.address as usize, r.address as usize + r.size as usize))
+        .collect();
+    let mut allocations: Vec<Allocation> = Vec::new();
+    let mut errors: Vec<CompilerError> = Vec::new();
+
+    for variable in variables {
+        let size = variable.size as usize;
+        let mut address = *ram.start() as usize;
+
+        // skip past anything in the way until there is a gap that fits
+        while let Some(&(_, after)) = taken.iter().find(|(start, stop)| address < *stop && *start < address + size) {
+            address = after;
+        }
+
+        if address + size > end {
+            errors.push(CompilerError::from_token(ErrorCode::RamOverflow(variable.name.clone(), variable.size), &variable.token, false)
+                .with_help(&format!("variables can use 0x{:04X} to 0x{:04X} and take up {} bytes in total", ram.start(), ram.end(), total)));
+            continue;
+        }
+
+        taken.push((address, address + size));
+        allocations.push(Allocation { name: variable.name.clone(), address: address as u16, size: variable.size });
+    }
+
+    if errors.is_empty() {
+        Ok(allocations)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{collections::HashMap, fs, path::{Component, Path, PathBuf}};
+
+use super::stdlib;
+
+/// Where the compiler reads source files from. Paths are relative to the folder the entry file is in
+pub trait SourceProvider {
+    /// Returns the contents of a file, or None if it can't be read
+    fn read(&self, path: &Path) -> Option<String>;
+
+    /// Returns the same path for every way of naming a file, so each file is only included once
+    fn canonical(&self, path: &Path) -> PathBuf {
+        normalize(path)
+    }
+}
+
+/// Reads sources from disk, relative to a folder
+pub struct FileSystem {
+    root: PathBuf
+}
+
+impl FileSystem {
+    pub fn new(root: &Path) -> FileSystem {
+        FileSystem {
+            root: root.to_path_buf()
+        }
+    }
+}
+
+impl SourceProvider for FileSystem {
+    fn read(&self, path: &Path) -> Option<String> {
+        fs::read_to_string(self.root.join(path)).ok()
+    }
+
+    fn canonical(&self, path: &Path) -> PathBuf {
+        fs::canonicalize(self.root.join(path)).unwrap_or_else(|_| normalize(path))
+    }
+}
+
+/// Sources held in memory, keyed by path
+impl SourceProvider for HashMap<String, String> {
+    fn read(&self, path: &Path) -> Option<String> {
+        self.get(normalize(path).to_str()?).cloned()
+    }
+}
+
+/// A file found for an include
+pub enum Include {
+    /// A file from the sources, with its path and contents
+    File(PathBuf, String),
+    /// A file from the standard library, with its name and contents
+    Library(&'static str, &'static str),
+}
+
+/// Finds the file an include refers to. It is looked for next to the including file, then in each include directory and then in the standard library
+pub fn find_include(file: &str, including_file: &str, include_dirs: &[PathBuf], sources: &dyn SourceProvider) -> Option<Include> {
+    let directory = Path::new(including_file).parent().unwrap_or(Path::new(""));
+
+    std::iter::once(directory)
+        .chain(include_dirs.iter().map(|d| d.as_path()))
+        .map(|d| normalize(&d.join(file)))
+        .find_map(|path| sources.read(&path).map(|contents| Include::File(path, contents)))
+        .or_else(|| stdlib::get(file).map(|(name, contents)| Include::Library(name, contents)))
+}
+
+/// Removes . and folder/.. from a path without touching the file system
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
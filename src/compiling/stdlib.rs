@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use super::sources::SourceProvider;
+
+/// The standard library is built into the compiler, so `@include std/math` works in every project
+const LIBRARY: [(&str, &str); 5] = [
+    ("std/math.bread", include_str!("std/math.bread")),
+    ("std/compare.bread", include_str!("std/compare.bread")),
+    ("std/int16.bread", include_str!("std/int16.bread")),
+    ("std/memory.bread", include_str!("std/memory.bread")),
+    ("std/decimal.bread", include_str!("std/decimal.bread")),
+];
+
+/// Returns the name and contents of a standard library file. The .bread extension is optional
+pub fn get(path: &str) -> Option<(&'static str, &'static str)> {
+    LIBRARY.iter()
+        .find(|(name, _)| *name == path || name.strip_suffix(".bread") == Some(path))
+        .copied()
+}
+
+/// Returns the contents of a file, looking in the standard library before `sources`
+pub fn read_source(sources: &dyn SourceProvider, file: &str) -> Option<String> {
+    match get(file) {
+        Some((_, contents)) => Some(contents.to_string()),
+        None => sources.read(Path::new(file)),
+    }
+}
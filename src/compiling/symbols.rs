@@ -0,0 +1,80 @@
+use core::fmt;
+
+use super::{compiler::ListingEntry, lexer::Token};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Subroutine,
+    Label,
+    Constant,
+    Variable
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolKind::Subroutine => write!(f, "subroutine"),
+            SymbolKind::Label => write!(f, "label"),
+            SymbolKind::Constant => write!(f, "constant"),
+            SymbolKind::Variable => write!(f, "variable"),
+        }
+    }
+}
+
+/// A name defined in the program along with where it was defined
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u16,
+    pub kind: SymbolKind,
+    pub file: String,
+    pub line: i32
+}
+
+impl Symbol {
+    pub fn new(name: &str, value: u16, kind: SymbolKind, token: &Token) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            value,
+            kind,
+            file: token.file.clone(),
+            line: token.line
+        }
+    }
+}
+
+/// The RAM given to a variable
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub name: String,
+    pub address: u16,
+    pub size: u16,
+}
+
+/// Formats the symbols and the address to source line map as a plain text file.
+/// 
+/// Every line is made of space separated fields with the source location last, so file names may contain spaces:
+/// ```text
+/// symbol <value> <kind> <name> <file>:<line>
+/// line <address> <size> <file>:<line>
+/// ```
+pub fn symbol_file(symbols: &[Symbol], listing: &[ListingEntry]) -> String {
+    let mut out = String::from("# BreadLang symbol file\n");
+
+    let mut symbols: Vec<&Symbol> = symbols.iter().collect();
+    symbols.sort_by_key(|s| s.value);
+
+    out += "# symbol <value> <kind> <name> <file>:<line>\n";
+    for symbol in symbols {
+        out += &format!("symbol 0x{:04X} {} {} {}:{}\n", symbol.value, symbol.kind, symbol.name, symbol.file, symbol.line);
+    }
+
+    out += "# line <address> <size> <file>:<line>\n";
+    for entry in listing {
+        if let ListingEntry::Instruction(line) = entry {
+            out += &format!("line 0x{:04X} {} {}:{}\n", line.address, line.size, line.file, line.line);
+        }
+    }
+
+    out
+}
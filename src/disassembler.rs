@@ -0,0 +1,263 @@
+use std::collections::{BTreeSet, HashSet};
+
+use enum_primitive::FromPrimitive;
+
+use crate::compiling::{compiler::Compiler, Instruction, Register};
+
+/// A single decoded line of the program.
+enum Line {
+    Instruction(DecodedInstruction),
+    Raw(u8)
+}
+
+struct DecodedInstruction {
+    address: u16,
+    instruction: Instruction,
+    immediate: bool,
+    register: Register,
+    register_b: Option<Register>,
+    imm8: Option<u8>,
+    imm16: Option<u16>
+}
+
+impl DecodedInstruction {
+    /// Returns the address this instruction jumps to, if it is known at compile time
+    fn jump_target(&self) -> Option<u16> {
+        match self.instruction {
+            Instruction::JMP | Instruction::JZ | Instruction::JC if self.immediate => self.imm16,
+            _ => None
+        }
+    }
+
+    /// Returns true if this instruction jumps to the address in H:L
+    fn jumps_to_hl(&self) -> bool {
+        matches!(self.instruction, Instruction::JMP | Instruction::JZ | Instruction::JC) && !self.immediate
+    }
+
+    /// Encodes the instruction the same way the compiler would. Bits the compiler never sets are left out,
+    /// so an encoding it can't produce won't match the bytes it was decoded from
+    fn encode(&self) -> Vec<u8> {
+        let mut compiler = Compiler::new();
+
+        match self.instruction {
+            Instruction::MW | Instruction::ADD | Instruction::SUB if !self.immediate => {
+                compiler.two_bytes(self.instruction, false, self.register, self.register_b.unwrap());
+            }
+            Instruction::MW | Instruction::ADD | Instruction::SUB => {
+                compiler.first_byte(self.instruction, true, Some(self.register));
+            }
+            Instruction::NOP | Instruction::HLT => compiler.first_byte(self.instruction, false, None),
+            Instruction::LDA => compiler.first_byte(self.instruction, true, None),
+            Instruction::JMP | Instruction::JC => compiler.first_byte(self.instruction, self.immediate, None),
+            Instruction::PUSH | Instruction::OUT if self.immediate => {
+                compiler.first_byte(self.instruction, true, None);
+            }
+            Instruction::PUSH | Instruction::OUT | Instruction::POP => {
+                compiler.first_byte(self.instruction, false, Some(self.register));
+            }
+            Instruction::LW | Instruction::SW | Instruction::JZ => {
+                compiler.first_byte(self.instruction, self.immediate, Some(self.register));
+            }
+        }
+
+        if let Some(imm) = self.imm8 {
+            compiler.add_byte(imm);
+        }
+        if let Some(imm) = self.imm16 {
+            compiler.add_bytes(&imm.to_be_bytes());
+        }
+
+        compiler.bytes
+    }
+
+    fn size(&self) -> usize {
+        self.encode().len()
+    }
+
+    fn to_source(&self, label: &dyn Fn(u16) -> String) -> String {
+        let name = format!("{:?}", self.instruction);
+        let reg = format!("{:?}", self.register);
+
+        match self.instruction {
+            Instruction::NOP | Instruction::HLT => name,
+            Instruction::LW | Instruction::SW => match self.imm16 {
+                Some(address) => format!("{} {} {}", name, reg, address),
+                None => format!("{} {}", name, reg),
+            },
+            Instruction::MW | Instruction::ADD | Instruction::SUB => match self.imm8 {
+                Some(imm) => format!("{} {} {}", name, reg, imm),
+                None => format!("{} {} {:?}", name, reg, self.register_b.unwrap()),
+            },
+            Instruction::PUSH | Instruction::OUT => match self.imm8 {
+                Some(imm) => format!("{} {}", name, imm),
+                None => format!("{} {}", name, reg),
+            },
+            Instruction::POP => format!("{} {}", name, reg),
+            Instruction::LDA => match self.imm16 {
+                Some(address) => format!("{} {}", name, label(address)),
+                None => name,
+            },
+            Instruction::JMP | Instruction::JC => match self.imm16 {
+                Some(address) => format!("{} {}", name, label(address)),
+                None => name,
+            },
+            Instruction::JZ => match self.imm16 {
+                Some(address) => format!("{} {} {}", name, reg, label(address)),
+                None => format!("{} {}", name, reg),
+            },
+        }
+    }
+}
+
+/// Decodes the instruction at the start of bytes. Returns None if the bytes
+/// are not something the compiler could have produced.
+fn decode(bytes: &[u8], address: u16) -> Option<DecodedInstruction> {
+    let byte = *bytes.first()?;
+
+    let instruction = Instruction::from_u8((byte & 0b11111_0_00) >> 3)?;
+    let immediate = byte & 0b00000_1_00 != 0;
+    let register = Register::from_u8(byte & 0b00000_0_11).unwrap();
+
+    let mut decoded = DecodedInstruction {
+        address,
+        instruction,
+        immediate,
+        register,
+        register_b: None,
+        imm8: None,
+        imm16: None
+    };
+
+    match instruction {
+        Instruction::MW | Instruction::ADD | Instruction::SUB => {
+            let second = *bytes.get(1)?;
+            if immediate {
+                decoded.imm8 = Some(second);
+            } else {
+                decoded.register_b = Some(Register::from_u8((second & 0b11_000000) >> 6).unwrap());
+            }
+        }
+        Instruction::PUSH | Instruction::OUT if immediate => {
+            decoded.imm8 = Some(*bytes.get(1)?);
+        }
+        Instruction::LW | Instruction::SW | Instruction::LDA |
+            Instruction::JMP | Instruction::JZ | Instruction::JC if immediate => {
+            decoded.imm16 = Some(u16::from_be_bytes([*bytes.get(1)?, *bytes.get(2)?]));
+        }
+        _ => ()
+    }
+
+    // reject anything with stray bits set or that the compiler can't produce, like LDA without an address,
+    // it would not survive a round trip
+    let size = decoded.size();
+    if bytes.len() < size || decoded.encode() != bytes[..size] {
+        return None;
+    }
+
+    Some(decoded)
+}
+
+fn label_name(address: u16) -> String {
+    format!("_L{:04X}", address)
+}
+
+/// Turns compiled bytes back into BreadLang source
+pub fn disassemble(bytes: &[u8], source_name: &str) -> String {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut starts: HashSet<u16> = HashSet::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let address = position as u16;
+        match decode(&bytes[position..], address) {
+            Some(decoded) => {
+                starts.insert(address);
+                position += decoded.size();
+                lines.push(Line::Instruction(decoded));
+            }
+            None => {
+                lines.push(Line::Raw(bytes[position]));
+                position += 1;
+            }
+        }
+    }
+
+    // erased EEPROMs are full of 0xFF, don't print all of it. Only bytes after the last instruction are padding,
+    // an 0xFF before that can be an operand
+    let mut padding = 0;
+    while let Some(Line::Raw(0xFF)) = lines.last() {
+        lines.pop();
+        padding += 1;
+    }
+
+    // find all jump targets
+    let mut targets: BTreeSet<u16> = BTreeSet::new();
+    for i in 0..lines.len() {
+        if let Line::Instruction(decoded) = &lines[i] {
+            if let Some(target) = decoded.jump_target() {
+                targets.insert(target);
+            }
+
+            // LDA followed by a jump through H:L
+            if let (Instruction::LDA, Some(address)) = (decoded.instruction, decoded.imm16) {
+                if let Some(Line::Instruction(next)) = lines.get(i + 1) {
+                    if next.jumps_to_hl() {
+                        targets.insert(address);
+                    }
+                }
+            }
+        }
+    }
+
+    let label = |address: u16| -> String {
+        if targets.contains(&address) {
+            label_name(address)
+        } else {
+            format!("0x{:04X}", address)
+        }
+    };
+
+    let mut source = String::new();
+    source += &format!("; Disassembled from {}\n", source_name);
+
+    // targets that don't land on an instruction can't be a DEF
+    let constants: Vec<&u16> = targets.iter().filter(|t| !starts.contains(t)).collect();
+    if !constants.is_empty() {
+        source += "\n";
+        for address in constants {
+            source += &format!("const {} 0x{:04X}\n", label_name(*address), address);
+        }
+    }
+
+    if lines.iter().any(|l| matches!(l, Line::Raw(_))) {
+        source += "\n; Raw bytes could not be decoded and are left as comments.\n";
+        source += "; They will not be present if this file is compiled again.\n";
+    }
+
+    source += "\nmain:\n";
+
+    let mut address: u16 = 0;
+    for line in &lines {
+        if targets.contains(&address) && starts.contains(&address) {
+            source += &format!("\tDEF {}\n", label_name(address));
+        }
+
+        match line {
+            Line::Instruction(decoded) => {
+                source += &format!("\t{}\n", decoded.to_source(&label));
+                address = decoded.address + decoded.size() as u16;
+            }
+            Line::Raw(byte) => {
+                source += &format!("\t; raw byte 0x{:02X} (0b{:05b}_{:01b}_{:02b}) at 0x{:04X}\n",
+                    byte, byte >> 3, (byte >> 2) & 1, byte & 0b11, address);
+                address += 1;
+            }
+        }
+    }
+
+    if padding > 0 {
+        source += &format!("\t; {} bytes of 0xFF padding\n", padding);
+    }
+
+    source
+}
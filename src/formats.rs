@@ -0,0 +1,155 @@
+use core::fmt::Write;
+
+/// Bytes per record in the Intel HEX and S-record formats
+const RECORD_SIZE: usize = 16;
+/// Values per line in Logisim images
+const LOGISIM_LINE: usize = 8;
+
+/// EEPROM chips and how many bytes they hold
+const CHIPS: [(&str, usize); 3] = [
+    ("28c16", 2 * 1024),
+    ("28c64", 8 * 1024),
+    ("28c256", 32 * 1024),
+];
+
+/// The ways a ROM image can be written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The bytes as they are
+    Binary,
+    IntelHex,
+    SRecord,
+    /// Logisim "v2.0 raw", which can be loaded into a ROM component
+    Logisim,
+    /// The bytes followed by the fill byte up to the size of the chip
+    Padded(usize),
+}
+
+impl Format {
+    /// Looks up a format by the name given to --format. `padded` fills up to `rom_size`
+    pub fn from_name(name: &str, rom_size: usize) -> Result<Format, String> {
+        let name = name.to_lowercase();
+
+        match name.as_str() {
+            "binary" => Ok(Format::Binary),
+            "ihex" => Ok(Format::IntelHex),
+            "srec" => Ok(Format::SRecord),
+            "logisim" => Ok(Format::Logisim),
+            "padded" => Ok(Format::Padded(rom_size)),
+            _ => match CHIPS.iter().find(|(chip, _)| *chip == name) {
+                Some((_, size)) => Ok(Format::Padded(*size)),
+                None => Err(format!("Unknown output format {}", name)),
+            },
+        }
+    }
+
+    /// Writes the image in this format. `fill` is used for padding
+    pub fn write(&self, bytes: &[u8], fill: u8) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Binary => Ok(bytes.to_vec()),
+            Format::IntelHex => Ok(intel_hex(bytes).into_bytes()),
+            Format::SRecord => Ok(s_record(bytes).into_bytes()),
+            Format::Logisim => Ok(logisim(bytes).into_bytes()),
+            Format::Padded(size) => {
+                if bytes.len() > *size {
+                    return Err(format!("The image is {} bytes but the chip only holds {}", bytes.len(), size));
+                }
+
+                let mut padded = bytes.to_vec();
+                padded.resize(*size, fill);
+                Ok(padded)
+            }
+        }
+    }
+}
+
+/// `:LLAAAATT<data>CC` records, ending with an end of file record.
+/// Images over 64 KiB, such as the microcode, get extended linear address records
+fn intel_hex(bytes: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (i, chunk) in bytes.chunks(RECORD_SIZE).enumerate() {
+        let address = i * RECORD_SIZE;
+
+        if address > 0xFFFF && address.is_multiple_of(0x10000) {
+            out += &intel_hex_line(0, 0x04, &((address >> 16) as u16).to_be_bytes());
+        }
+        out += &intel_hex_line(address as u16, 0x00, chunk);
+    }
+
+    out + &intel_hex_line(0, 0x01, &[])
+}
+
+fn intel_hex_line(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+
+    // the checksum makes every byte of the record add up to 0
+    let sum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    record.push(sum.wrapping_neg());
+
+    format!(":{}\n", hex(&record))
+}
+
+/// S0 header, data and end records. Images over 64 KiB use 24 bit addresses (S2 and S8) instead of 16 bit ones (S1 and S9)
+fn s_record(bytes: &[u8]) -> String {
+    let wide = bytes.len() > 0x10000;
+    let (data, end) = if wide { ('2', '8') } else { ('1', '9') };
+
+    let mut out = s_record_line('0', 0, false, &[]);
+
+    for (i, chunk) in bytes.chunks(RECORD_SIZE).enumerate() {
+        out += &s_record_line(data, (i * RECORD_SIZE) as u32, wide, chunk);
+    }
+
+    out + &s_record_line(end, 0, wide, &[])
+}
+
+fn s_record_line(kind: char, address: u32, wide: bool, data: &[u8]) -> String {
+    let address = if wide { &address.to_be_bytes()[1..] } else { &address.to_be_bytes()[2..] };
+
+    // the count includes the address and checksum
+    let mut record = vec![(address.len() + data.len() + 1) as u8];
+    record.extend_from_slice(address);
+    record.extend_from_slice(data);
+
+    let sum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    record.push(!sum);
+
+    format!("S{}{}\n", kind, hex(&record))
+}
+
+/// Lowercase hex values, with runs written as `count*value` like Logisim does
+fn logisim(bytes: &[u8]) -> String {
+    let mut out = String::from("v2.0 raw\n");
+    let mut values: Vec<String> = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let run = bytes[i..].iter().take_while(|b| **b == bytes[i]).count();
+
+        if run >= 4 {
+            values.push(format!("{}*{:x}", run, bytes[i]));
+            i += run;
+        } else {
+            values.push(format!("{:x}", bytes[i]));
+            i += 1;
+        }
+    }
+
+    for line in values.chunks(LOGISIM_LINE) {
+        out += &line.join(" ");
+        out += "\n";
+    }
+
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02X}", b);
+        out
+    })
+}
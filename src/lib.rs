@@ -0,0 +1,7 @@
+#[macro_use] extern crate enum_primitive;
+
+pub mod compiling;
+pub mod run;
+pub mod special_programs;
+pub mod disassembler;
+pub mod formats;
@@ -0,0 +1,510 @@
+mod analysis;
+
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+
+use breadlang::compiling::{compile_sources, compiler::ListingEntry, error_handler::{CompilerError, Location}, sources::{normalize, FileSystem, SourceProvider}, symbols::Symbol, CompiledProgram, Settings};
+use serde_json::{json, Value};
+
+use crate::{manifest::{Manifest, MANIFEST_FILE}, project};
+
+use self::analysis::{Definition, Index, Kind};
+
+const INSTRUCTIONS: [(&str, &str); 17] = [
+    ("NOP", "Does nothing"),
+    ("LW", "LW reg [address]: loads a byte from memory"),
+    ("SW", "SW reg [address]: stores a byte in memory"),
+    ("MW", "MW reg reg/imm: moves a value into a register"),
+    ("PUSH", "PUSH reg/imm: pushes a value onto the stack"),
+    ("POP", "POP reg: pops a value off the stack"),
+    ("LDA", "LDA address: loads an address into H and L"),
+    ("JMP", "JMP [address]: jumps to an address, or to H:L"),
+    ("JZ", "JZ reg [address]: jumps if the register is zero"),
+    ("JC", "JC [address]: jumps if the carry flag is set"),
+    ("ADD", "ADD reg reg/imm: adds a value to a register"),
+    ("SUB", "SUB reg reg/imm: subtracts a value from a register"),
+    ("OUT", "OUT reg/imm: shows a value on the display"),
+    ("HLT", "Stops the computer"),
+    ("CALL", "CALL subroutine: pushes the return address and jumps"),
+    ("RET", "Returns from a CALL. Clobbers H and L"),
+    ("DEF", "DEF label: marks the address of the next instruction"),
+];
+
+const KEYWORDS: [&str; 10] = ["@macro", "@include", "@var", "@reserve", "@if", "@ifdef", "@ifndef", "@else", "@endif", "const"];
+
+const REGISTERS: [&str; 4] = ["A", "B", "H", "L"];
+
+// LSP enums
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const MESSAGE_ERROR: u8 = 1;
+
+/// A project and how the compiler sees it
+struct Project {
+    /// the folder with Bread.toml in it, or the folder of a file that isn't in a project
+    root: PathBuf,
+    /// files are named relative to the folder the entry file is in
+    folder: PathBuf,
+    entry: String,
+    settings: Settings,
+}
+
+impl Project {
+    /// Finds the project a document belongs to by looking for Bread.toml or src/main.bread above it.
+    /// A document outside of any project is compiled on its own
+    fn find(document: &Path) -> Result<Project, String> {
+        let root = document.ancestors().skip(1)
+            .find(|folder| folder.join(MANIFEST_FILE).is_file() || folder.join("src/main.bread").is_file());
+
+        let Some(root) = root else {
+            let folder = document.parent().unwrap_or(Path::new("/")).to_path_buf();
+            let entry = document.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+            return Ok(Project { root: folder.clone(), folder, entry, settings: Settings::default() });
+        };
+
+        let options = Manifest::read(root)?.compile_options();
+        let settings = project::settings(root, &options)?;
+        let entry = root.join(&options.entry);
+
+        Ok(Project {
+            root: root.to_path_buf(),
+            folder: entry.parent().unwrap_or(root).to_path_buf(),
+            entry: entry.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            settings,
+        })
+    }
+
+    /// The name the compiler uses for a file
+    fn name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.folder).unwrap_or(path).to_string_lossy().to_string()
+    }
+
+    /// The path of a file the compiler named. Standard library files don't have one
+    fn path(&self, name: &str, sources: &dyn SourceProvider) -> Option<PathBuf> {
+        sources.read(Path::new(name))?;
+        Some(normalize(&self.folder.join(name)))
+    }
+}
+
+/// Open documents on top of the files on disk
+struct Workspace<'a> {
+    documents: &'a HashMap<PathBuf, String>,
+    folder: PathBuf,
+    disk: FileSystem,
+}
+
+impl<'a> Workspace<'a> {
+    fn new(documents: &'a HashMap<PathBuf, String>, project: &Project) -> Workspace<'a> {
+        Workspace {
+            documents,
+            folder: project.folder.clone(),
+            disk: FileSystem::new(&project.folder),
+        }
+    }
+}
+
+impl SourceProvider for Workspace<'_> {
+    fn read(&self, path: &Path) -> Option<String> {
+        match self.documents.get(&normalize(&self.folder.join(path))) {
+            Some(text) => Some(text.clone()),
+            None => self.disk.read(path),
+        }
+    }
+
+    fn canonical(&self, path: &Path) -> PathBuf {
+        self.disk.canonical(path)
+    }
+}
+
+struct Server {
+    /// open documents by path
+    documents: HashMap<PathBuf, String>,
+    /// the files each project published diagnostics for, so they can be cleared once fixed
+    published: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// the last program each project compiled to without errors
+    programs: HashMap<PathBuf, CompiledProgram>,
+    /// messages waiting to be sent
+    outgoing: Vec<Value>,
+}
+
+/// Runs a language server over stdin and stdout until the client says to exit
+pub fn lsp() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    let _ = serve(&mut stdin.lock(), &mut stdout.lock());
+}
+
+/// Answers JSON-RPC messages from `input` on `output`. Returns when the client exits or `input` ends
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let mut server = Server {
+        documents: HashMap::new(),
+        published: HashMap::new(),
+        programs: HashMap::new(),
+        outgoing: Vec::new(),
+    };
+
+    while let Some(message) = read_message(input)? {
+        let method = message["method"].as_str().unwrap_or("");
+        if method == "exit" {
+            break;
+        }
+
+        match message.get("id") {
+            Some(id) if !method.is_empty() => {
+                let response = match server.request(method, &message["params"]) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, msg)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": msg } }),
+                };
+                server.outgoing.push(response);
+            }
+            // responses to anything we sent, which is never a request
+            Some(_) => (),
+            None => server.notification(method, &message["params"]),
+        }
+
+        for message in server.outgoing.drain(..) {
+            write_message(output, &message)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length: Option<usize> = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap()];
+    input.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+impl Server {
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["@"] }
+                },
+                "serverInfo": { "name": "BreadLang", "version": env!("CARGO_PKG_VERSION") }
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => Ok(self.definition(params).unwrap_or(Value::Null)),
+            "textDocument/hover" => Ok(self.hover(params).unwrap_or(Value::Null)),
+            "textDocument/completion" => Ok(self.completion(params).unwrap_or(json!([]))),
+            _ => Err((-32601, format!("Unknown method {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let Some(path) = params["textDocument"]["uri"].as_str().and_then(uri_to_path) else {
+            return;
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(path.clone(), text.to_string());
+            }
+            "textDocument/didChange" => {
+                // the whole document is sent every time
+                if let Some(text) = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str()) {
+                    self.documents.insert(path.clone(), text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&path);
+            }
+            "textDocument/didSave" => (),
+            _ => return,
+        }
+
+        self.check(&path);
+    }
+
+    /// Compiles the project a document is in and publishes what is wrong with it
+    fn check(&mut self, document: &Path) {
+        let project = match Project::find(document) {
+            Ok(project) => project,
+            Err(msg) => {
+                self.notify("window/showMessage", json!({ "type": MESSAGE_ERROR, "message": msg }));
+                return;
+            }
+        };
+        let sources = Workspace::new(&self.documents, &project);
+
+        let problems = match compile_sources(&project.entry, &sources, &project.settings) {
+            Ok(mut program) => {
+                let warnings = std::mem::take(&mut program.warnings);
+                self.programs.insert(project.root.clone(), program);
+                warnings
+            }
+            Err(errors) => errors,
+        };
+
+        let mut diagnostics: HashMap<PathBuf, Vec<Value>> = HashMap::new();
+        for problem in &problems {
+            let location = problem.location();
+            if let Some(path) = project.path(&location.file, &sources) {
+                diagnostics.entry(path).or_default().push(diagnostic(problem, &project, &sources));
+            }
+        }
+
+        // files that had problems last time need to be told they are fixed
+        let previous = self.published.insert(project.root.clone(), diagnostics.keys().cloned().collect()).unwrap_or_default();
+        for path in previous {
+            diagnostics.entry(path).or_default();
+        }
+
+        for (path, diagnostics) in diagnostics {
+            self.notify("textDocument/publishDiagnostics", json!({ "uri": path_to_uri(&path), "diagnostics": diagnostics }));
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.outgoing.push(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Finds the project, the index and the token under the cursor for a request
+    fn lookup(&self, params: &Value) -> Option<(Project, Index, String, u32, u32)> {
+        let path = uri_to_path(params["textDocument"]["uri"].as_str()?)?;
+        let line = params["position"]["line"].as_u64()? as u32;
+        let character = params["position"]["character"].as_u64()? as u32;
+
+        let project = Project::find(&path).ok()?;
+        let sources = Workspace::new(&self.documents, &project);
+        let file = project.name(&path);
+
+        let index = Index::new(&[project.entry.clone(), file.clone()], &project.settings.include_dirs, &sources);
+        Some((project, index, file, line, character))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (project, index, file, line, character) = self.lookup(params)?;
+        let sources = Workspace::new(&self.documents, &project);
+        let token = index.token_at(&file, line, character)?;
+
+        if let Some(link) = index.link_at(token) {
+            let path = project.path(link.file.as_ref()?, &sources)?;
+            return Some(json!({ "uri": path_to_uri(&path), "range": range(0, 0, 0) }));
+        }
+
+        let definition = index.definition_of(token)?;
+        let path = project.path(&definition.token.file, &sources)?;
+        let location = Location {
+            file: definition.token.file.clone(),
+            line: definition.token.line,
+            column: definition.token.column,
+            length: definition.token.len() as u32,
+        };
+
+        Some(json!({ "uri": path_to_uri(&path), "range": location_range(&location) }))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (project, index, file, line, character) = self.lookup(params)?;
+        let token = index.token_at(&file, line, character)?;
+        let program = self.programs.get(&project.root);
+
+        let text = match index.definition_of(token) {
+            Some(definition) => describe(definition, program),
+            None => encoding(&file, token.line, program?)?,
+        };
+
+        Some(json!({ "contents": { "kind": "markdown", "value": text } }))
+    }
+
+    fn completion(&self, params: &Value) -> Option<Value> {
+        let (_, index, file, line, _) = self.lookup(params)?;
+        let mut items: Vec<Value> = Vec::new();
+
+        // LSP completion item kinds
+        for (name, detail) in INSTRUCTIONS {
+            items.push(json!({ "label": name, "kind": 14, "detail": detail }));
+        }
+        for keyword in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": 14 }));
+        }
+        for register in REGISTERS {
+            items.push(json!({ "label": register, "kind": 6, "detail": "register" }));
+        }
+
+        let mut seen: HashSet<&str> = HashSet::new();
+        for definition in index.visible(&file, line as i32 + 1) {
+            if !seen.insert(&definition.name) {
+                continue;
+            }
+
+            let kind = match definition.kind {
+                Kind::Subroutine | Kind::Macro => 3,
+                Kind::Constant => 21,
+                Kind::Variable => 6,
+                Kind::Label => 18,
+                Kind::Argument => 6,
+            };
+            items.push(json!({ "label": definition.name, "kind": kind, "detail": definition.signature }));
+        }
+
+        Some(Value::Array(items))
+    }
+}
+
+/// Turns a compiler error into an LSP diagnostic. Notes become related information and help is added to the message
+fn diagnostic(error: &CompilerError, project: &Project, sources: &dyn SourceProvider) -> Value {
+    let mut message = error.code().to_string();
+    let mut related: Vec<Value> = Vec::new();
+
+    for note in error.notes() {
+        match note.location.as_ref().and_then(|l| project.path(&l.file, sources).map(|p| (l, p))) {
+            Some((location, path)) => related.push(json!({
+                "location": { "uri": path_to_uri(&path), "range": location_range(location) },
+                "message": note.message
+            })),
+            None => message += &format!("\nhelp: {}", note.message),
+        }
+    }
+
+    json!({
+        "range": location_range(&error.location()),
+        "severity": if error.is_warning() { SEVERITY_WARNING } else { SEVERITY_ERROR },
+        "source": "BreadLang",
+        "message": message,
+        "relatedInformation": related
+    })
+}
+
+/// Columns count characters, which is the same as UTF-16 for the ASCII that programs are written in
+fn location_range(location: &Location) -> Value {
+    let line = (location.line - 1).max(0) as u32;
+    let start = (location.column - 1).max(0) as u32;
+    let end = if location.column > 0 { start + location.length } else { start };
+
+    range(line, start, end)
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end }
+    })
+}
+
+/// Markdown for hovering over a name, with its address or value when the program has compiled
+fn describe(definition: &Definition, program: Option<&CompiledProgram>) -> String {
+    let mut text = format!("```breadlang\n{}\n```\n", definition.signature);
+
+    let symbol: Option<&Symbol> = program.and_then(|p| p.symbols.iter().find(|s| {
+        s.name == definition.name && s.file == definition.token.file && s.line == definition.token.line
+    }));
+
+    match (definition.kind, symbol) {
+        (Kind::Subroutine, Some(s)) => text += &format!("subroutine at `0x{:04X}`\n", s.value),
+        (Kind::Label, Some(s)) => text += &format!("label at `0x{:04X}`\n", s.value),
+        (Kind::Constant, Some(s)) => text += &format!("constant `0x{:04X}` ({})\n", s.value, s.value),
+        (Kind::Variable, Some(s)) => text += &format!("variable at `0x{:04X}`\n", s.value),
+        (Kind::Macro, _) => text += "macro\n",
+        (Kind::Argument, _) => text += &format!("argument of {}\n", definition.scope.as_deref().unwrap_or("")),
+        (kind, None) => text += &format!("{:?}\n", kind).to_lowercase(),
+    }
+
+    if !definition.comment.is_empty() {
+        text += "\n---\n";
+        text += &definition.comment;
+        text += "\n";
+    }
+
+    text
+}
+
+/// Markdown showing the address and bytes of every instruction compiled from a line
+fn encoding(file: &str, line: i32, program: &CompiledProgram) -> Option<String> {
+    const SHOWN: usize = 8;
+
+    let lines: Vec<String> = program.listing.iter()
+        .filter_map(|entry| match entry {
+            ListingEntry::Instruction(source) if source.file == file && source.line == line => Some(source),
+            _ => None,
+        })
+        .map(|source| {
+            let bytes = &program.bytes[source.address as usize..(source.address + source.size) as usize];
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let binary: Vec<String> = bytes.iter().map(|b| format!("{:08b}", b)).collect();
+
+            format!("0x{:04X}  {}  {}", source.address, hex.join(" "), binary.join(" "))
+        })
+        .collect();
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut text = format!("```text\n{}\n```\n", lines.iter().take(SHOWN).cloned().collect::<Vec<String>>().join("\n"));
+    if lines.len() > SHOWN {
+        text += &format!("and {} more uses\n", lines.len() - SHOWN);
+    }
+
+    Some(text)
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    // %XX escapes are bytes of UTF-8
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chars = path.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    Some(normalize(Path::new(&String::from_utf8(bytes).ok()?)))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri += &format!("%{:02X}", b);
+        }
+    }
+
+    uri
+}
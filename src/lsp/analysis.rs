@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use breadlang::compiling::{lexer::{scan_tokens, Token, TokenType}, sources::{find_include, Include, SourceProvider}};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Subroutine,
+    Macro,
+    Constant,
+    Variable,
+    Label,
+    Argument
+}
+
+/// A name defined somewhere in the project
+pub struct Definition {
+    pub name: String,
+    pub kind: Kind,
+    /// the subroutine or macro a label or argument belongs to
+    pub scope: Option<String>,
+    pub token: Token,
+    /// the line that defines it, such as `jeq(x y target)`
+    pub signature: String,
+    /// comment lines right above the definition
+    pub comment: String,
+}
+
+/// The path in an `@include` and the name of the file it found
+pub struct Link {
+    pub token: Token,
+    pub file: Option<String>,
+}
+
+struct File {
+    name: String,
+    tokens: Vec<Token>,
+    /// subroutines and macros along with the line they start on
+    scopes: Vec<(i32, String)>,
+}
+
+/// Everything defined in a set of files. It only needs the tokens, so it still works while the program has errors
+pub struct Index {
+    files: Vec<File>,
+    pub definitions: Vec<Definition>,
+    pub links: Vec<Link>,
+}
+
+impl Index {
+    /// Scans each file in `entries` and every file they include
+    pub fn new(entries: &[String], include_dirs: &[PathBuf], sources: &dyn SourceProvider) -> Index {
+        let mut index = Index {
+            files: Vec::new(),
+            definitions: Vec::new(),
+            links: Vec::new(),
+        };
+
+        let mut pending: Vec<(String, Option<String>)> = entries.iter().map(|e| (e.clone(), None)).collect();
+
+        while let Some((name, contents)) = pending.pop() {
+            if index.files.iter().any(|f| f.name == name) {
+                continue;
+            }
+
+            let Some(text) = contents.or_else(|| sources.read(name.as_ref())) else {
+                continue;
+            };
+
+            let start = index.links.len();
+            index.scan(name, &text);
+
+            for link in &mut index.links[start..] {
+                let TokenType::Identifier(path) = &link.token.token_type else {
+                    continue;
+                };
+
+                let (file, contents) = match find_include(path, &link.token.file, include_dirs, sources) {
+                    Some(Include::File(path, contents)) => (path.to_string_lossy().to_string(), contents),
+                    Some(Include::Library(name, contents)) => (name.to_string(), contents.to_string()),
+                    None => continue,
+                };
+
+                link.file = Some(file.clone());
+                pending.push((file, Some(contents)));
+            }
+        }
+
+        index
+    }
+
+    /// Finds the definitions in a file, one line at a time
+    fn scan(&mut self, name: String, text: &str) {
+        let (tokens, _) = scan_tokens(text.to_string(), name.clone());
+        let lines: Vec<&str> = text.lines().collect();
+
+        let mut scopes: Vec<(i32, String)> = Vec::new();
+        let mut after_macro = false;
+        let mut in_subroutine = false;
+
+        for line in tokens.split(|t| matches!(t.token_type, TokenType::NewLine | TokenType::EndOfFile)) {
+            let indented = matches!(line.first().map(|t| &t.token_type), Some(TokenType::Indent(_)));
+            let line: Vec<&Token> = line.iter().filter(|t| !matches!(t.token_type, TokenType::Indent(_))).collect();
+
+            let Some(first) = line.first() else {
+                continue;
+            };
+
+            let source = lines.get(first.line as usize - 1).copied().unwrap_or("");
+            let scope = scopes.last().map(|(_, name)| name.clone());
+            let is_macro = after_macro;
+            after_macro = false;
+
+            match (&first.token_type, line.get(1).map(|t| &t.token_type)) {
+                (TokenType::Macro, _) => after_macro = true,
+
+                (TokenType::Include, Some(TokenType::Identifier(_))) => self.links.push(Link {
+                    token: line[1].clone(),
+                    file: None,
+                }),
+
+                (TokenType::Constant, Some(TokenType::Identifier(constant))) =>
+                    self.define(constant, Kind::Constant, None, line[1], source, &lines),
+
+                (TokenType::Variable, Some(TokenType::Identifier(variable))) =>
+                    self.define(variable, Kind::Variable, None, line[1], source, &lines),
+
+                // like the compiler, .loop in a subroutine is called main.loop
+                (TokenType::Def, Some(TokenType::Identifier(label))) => match scope {
+                    Some(scope) if in_subroutine && label.starts_with('.') =>
+                        self.define(&format!("{}{}", scope, label), Kind::Label, Some(scope), line[1], source, &lines),
+                    _ => self.define(label, Kind::Label, scope, line[1], source, &lines),
+                },
+
+                // subroutines and macros are the only lines that end in a colon
+                (TokenType::Identifier(header), _) if !indented && matches!(line.last().unwrap().token_type, TokenType::Colon) => {
+                    let kind = if is_macro { Kind::Macro } else { Kind::Subroutine };
+                    self.define(header, kind, None, first, source, &lines);
+
+                    for argument in &line[1..] {
+                        if let TokenType::Identifier(name) = &argument.token_type {
+                            self.define(name, Kind::Argument, Some(header.clone()), argument, source, &lines);
+                        }
+                    }
+
+                    scopes.push((first.line, header.clone()));
+                    in_subroutine = !is_macro;
+                }
+
+                _ => (),
+            }
+        }
+
+        self.files.push(File { name, tokens, scopes });
+    }
+
+    fn define(&mut self, name: &str, kind: Kind, scope: Option<String>, token: &Token, source: &str, lines: &[&str]) {
+        // comments directly above a definition describe it
+        let mut comment: Vec<&str> = lines[..token.line as usize - 1].iter().rev()
+            .map(|l| l.trim())
+            .skip_while(|l| kind == Kind::Macro && *l == "@macro")
+            .take_while(|l| l.starts_with(';'))
+            .map(|l| l.trim_start_matches(';').trim())
+            .collect();
+        comment.reverse();
+
+        self.definitions.push(Definition {
+            name: name.to_string(),
+            kind,
+            scope,
+            token: token.clone(),
+            signature: source.trim().trim_end_matches(':').to_string(),
+            comment: comment.join("\n"),
+        });
+    }
+
+    /// Returns the token at a 0 based line and character, including the character just past its end
+    pub fn token_at(&self, file: &str, line: u32, character: u32) -> Option<&Token> {
+        let file = self.files.iter().find(|f| f.name == file)?;
+        let line = line as i32 + 1;
+        let character = character as i32 + 1;
+
+        file.tokens.iter()
+            .filter(|t| t.line == line && t.column > 0 && !matches!(t.token_type, TokenType::NewLine | TokenType::EndOfFile | TokenType::Indent(_)))
+            .find(|t| t.column <= character && character <= t.column + t.len() as i32)
+    }
+
+    /// Returns the subroutine or macro that a line of a file is in
+    pub fn scope_at(&self, file: &str, line: i32) -> Option<&str> {
+        self.files.iter().find(|f| f.name == file)?
+            .scopes.iter()
+            .take_while(|(start, _)| *start <= line)
+            .last()
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Finds what an identifier refers to. Labels and arguments in the same scope come before anything global
+    pub fn definition_of(&self, token: &Token) -> Option<&Definition> {
+        let TokenType::Identifier(name) = &token.token_type else {
+            return None;
+        };
+        let scope = self.scope_at(&token.file, token.line);
+        let qualified = match scope {
+            Some(scope) if name.starts_with('.') => format!("{}{}", scope, name),
+            _ => name.clone(),
+        };
+
+        self.definitions.iter()
+            .filter(|d| d.name == *name || d.name == qualified)
+            .find(|d| matches!(d.kind, Kind::Label | Kind::Argument) && d.scope.as_deref() == scope && d.token.file == token.file)
+            .or_else(|| self.definitions.iter()
+                .filter(|d| d.name == *name)
+                .find(|d| is_global(d)))
+    }
+
+    /// Returns the include link at a token, if it is the path of an include
+    pub fn link_at(&self, token: &Token) -> Option<&Link> {
+        self.links.iter().find(|l| l.token.file == token.file && l.token.start == token.start)
+    }
+
+    /// Everything that can be used at a line: global names along with the labels and arguments of its scope
+    pub fn visible(&self, file: &str, line: i32) -> Vec<&Definition> {
+        let scope = self.scope_at(file, line);
+
+        self.definitions.iter().filter(|d| match d.kind {
+            Kind::Label | Kind::Argument => is_global(d) || (d.scope.as_deref() == scope && d.token.file == file),
+            _ => true,
+        }).collect()
+    }
+}
+
+/// Checks if a definition can be used from anywhere, which includes labels like main.loop
+fn is_global(definition: &Definition) -> bool {
+    match definition.kind {
+        Kind::Subroutine | Kind::Macro | Kind::Constant | Kind::Variable => true,
+        Kind::Label => definition.scope.as_ref().is_some_and(|scope| definition.name.starts_with(&format!("{}.", scope))),
+        Kind::Argument => false,
+    }
+}
@@ -1,23 +1,33 @@
-#[macro_use] extern crate enum_primitive;
-
-use std::{env, fs::{self, DirBuilder, File}, io::Write, path::Path};
+use std::{env, fs::{self, DirBuilder, File}, io::Write, path::{Path, PathBuf}, process};
 
+use breadlang::{compiling::{error_handler, formatter, parse_number, sources::FileSystem}, disassembler::disassemble, formats::Format, run::run, special_programs};
 use colored::Colorize;
-use compiling::error_handler;
 
-use crate::{compiling::compile, run::run, upload::upload};
+use crate::{manifest::Manifest, project::{compile, CompileOptions}, upload::upload, watch::watch};
 
-pub mod compiling;
-pub mod run;
 mod upload;
-mod special_programs;
+mod manifest;
+mod project;
+mod lsp;
+mod watch;
 
 fn main() {
-    let v = env!("CARGO_PKG_VERSION");
-    println!("{}", format!("BreadLang v{}", v).yellow().bold());
-
     let args: Vec<String> = env::args().collect();
 
+    // the language server talks over stdout, so nothing else can be printed
+    if args.get(1).map(|a| a.as_str()) == Some("lsp") {
+        lsp::lsp();
+        return;
+    }
+
+    // disasm without an output file prints the source, which has to compile again if it is piped to a file
+    let disasm_to_stdout = args.get(1).map(|a| a.as_str()) == Some("disasm") && args.len() == 3;
+
+    if !disasm_to_stdout {
+        let v = env!("CARGO_PKG_VERSION");
+        println!("{}", format!("BreadLang v{}", v).yellow().bold());
+    }
+
     if args.len() <= 1 {
         usage();
         return;
@@ -30,69 +40,120 @@ fn main() {
         arguments.push(args[i].clone());
     }
 
+    let manifest = match command.as_str() {
+        "run" | "build" | "upload" | "fmt" => match Manifest::load() {
+            Ok(manifest) => manifest,
+            Err(_) => return,
+        },
+        _ => Manifest::default(),
+    };
+
     match command.as_str() {
         "run" => {
             let debug:bool = arguments.contains(&String::from("--debug"));
 
-            if let Ok(bytecode) = build() {
-                run(bytecode, debug);
+            if let Ok(options) = compile_options(&manifest, &arguments) {
+                if arguments.contains(&String::from("--watch")) {
+                    watch(&options, true, debug);
+                } else if let Ok(bytecode) = build(&options, &mut Vec::new()) {
+                    run(bytecode, debug);
+                }
             }
         },
         "build" => {
-            let _ = build();
+            if let Ok(options) = compile_options(&manifest, &arguments) {
+                if arguments.contains(&String::from("--watch")) {
+                    watch(&options, false, false);
+                } else {
+                    let _ = build(&options, &mut Vec::new());
+                }
+            }
         },
         "new" => new(),
+        "fmt" => {
+            let check = arguments.contains(&String::from("--check"));
+            if !fmt(&manifest, &arguments, check) {
+                process::exit(1);
+            }
+        },
         "upload" => {
             let program: Vec<u8>;
-            if arguments.len() > 0 {
+            match special_program(&arguments) {
+                Some(Ok((p, _))) => program = p,
+                Some(Err(_)) => return,
                 // read file to upload
-                match arguments[0].as_str() {
-                    "--display" => {
-                        program = special_programs::segment_display();
+                None if arguments.first().is_some_and(|a| !a.starts_with('-')) => match fs::read(&arguments[0]) {
+                    Ok(p) => {
+                        program = p;
                     },
-                    "--brain" => {
-                        if arguments.len() < 2 {
-                            usage();
-                            return;
-                        } else {
-                            let input = &arguments[1];
-                            let byte_select: u8;
-                            if let Ok(num) = input.trim().parse::<u8>() {
-                                byte_select = num;
-                            } else {
-                                error_handler::print_error("Invalid number");
-                                return;
-                            }
-                            program = match special_programs::brain::get_program(byte_select) {
-                                Ok(p) => p,
-                                Err(msg) => {
-                                    error_handler::print_error(&msg);
-                                    return;
-                                }
-                            }
-                        }
+                    Err(e) => {
+                        error_handler::print_error(&format!("Failed to read file {}", e));
+                        return;
                     }
-                    _ => {
-                        match fs::read(&arguments[0]) {
-                            Ok(p) => {
-                                program = p;
-                            },
-                            Err(e) => {
-                                error_handler::print_error(&format!("Failed to read file {}", e));
-                                return;
-                            }
-                        }
+                },
+                // build the project with the options given
+                None => {
+                    match compile_options(&manifest, &arguments).and_then(|options| build(&options, &mut Vec::new())) {
+                        Ok(b) => program = b,
+                        Err(_) => return,
                     }
                 }
-            } else {
-                if let Ok(b) = build() {
-                    program = b;
-                } else {
+            }
+            
+            upload(program, manifest.upload.port.as_deref(), manifest.upload.baud.unwrap_or(9600));
+        },
+        "export" => {
+            let (program, used) = match special_program(&arguments) {
+                Some(Ok(special)) => special,
+                Some(Err(_)) => return,
+                None => {
+                    usage();
+                    return;
+                }
+            };
+
+            let Some(output) = arguments.get(used) else {
+                usage();
+                return;
+            };
+            let Ok(options) = compile_options(&manifest, &arguments[used + 1..]) else {
+                return;
+            };
+
+            let image = Format::from_name(&options.format, options.rom_size)
+                .and_then(|format| format.write(&program, options.fill));
+
+            match image.map(|image| fs::write(output, image)) {
+                Ok(Ok(())) => println!("{}", "Finished".green().bold()),
+                Ok(Err(e)) => error_handler::print_error(&format!("Failed to write file {}", e)),
+                Err(msg) => error_handler::print_error(&msg),
+            }
+        },
+        "disasm" => {
+            if arguments.is_empty() {
+                usage();
+                return;
+            }
+
+            let program = match fs::read(&arguments[0]) {
+                Ok(p) => p,
+                Err(e) => {
+                    error_handler::print_error(&format!("Failed to read file {}", e));
+                    return;
+                }
+            };
+
+            let source = disassemble(&program, &arguments[0]);
+
+            if let Some(output) = arguments.get(1) {
+                if let Err(e) = fs::write(output, source) {
+                    error_handler::print_error(&format!("Failed to write file {}", e));
                     return;
                 }
+                println!("{}", "Finished".green().bold());
+            } else {
+                print!("{}", source);
             }
-            
-            upload(program);
         },
         _ => usage(),
     }
@@ -102,6 +163,7 @@ fn new() {
     let src = Path::new("src");
     let main = Path::new("src/main.bread");
     let gitignore = Path::new(".gitignore");
+    let manifest = Path::new(manifest::MANIFEST_FILE);
 
     if src.exists() {
         error_handler::print_error("src folder already exists");
@@ -111,6 +173,10 @@ fn new() {
         error_handler::print_error(".gitignore already exists");
         return;
     }
+    if manifest.exists() {
+        error_handler::print_error(&format!("{} already exists", manifest::MANIFEST_FILE));
+        return;
+    }
 
     // make src folder
     DirBuilder::new().create(src).unwrap();
@@ -123,12 +189,196 @@ fn new() {
     let mut main_out = File::create(main).unwrap();
     write!(main_out, "main:\n\tHLT").unwrap();
 
+    // make Bread.toml, named after the folder
+    let name = env::current_dir().ok()
+        .and_then(|d| d.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or(String::from("project"));
+    fs::write(manifest, manifest::default_manifest(&name)).unwrap();
+
     println!("{}", "Finished".green().bold());
 }
 
-fn build() -> Result<Vec<u8>, ()> {
-    println!("Compiling project");
-    let bytecode = compile();
+/// Formats the files given, or every file next to the entry file. With `check` nothing is written.
+/// Returns false if a file couldn't be formatted, or needs formatting when checking
+fn fmt(manifest: &Manifest, arguments: &[String], check: bool) -> bool {
+    let mut files: Vec<PathBuf> = arguments.iter().filter(|a| !a.starts_with("--")).map(PathBuf::from).collect();
+
+    if files.is_empty() {
+        let options = manifest.compile_options();
+        let folder = Path::new(&options.entry).parent().unwrap_or(Path::new(""));
+        source_files(folder, &mut files);
+        files.sort();
+    }
+
+    let sources = FileSystem::new(Path::new(""));
+    let mut ok = true;
+
+    for file in &files {
+        let name = file.to_string_lossy().to_string();
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                error_handler::print_error(&format!("Failed to read file {} {}", name, e));
+                ok = false;
+                continue;
+            }
+        };
+
+        let formatted = match formatter::format(&text, &name) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for error in errors {
+                    error.print(&sources);
+                }
+                ok = false;
+                continue;
+            }
+        };
+
+        if formatted == text {
+            continue;
+        }
+
+        if check {
+            println!("{} needs formatting", name);
+            ok = false;
+        } else if let Err(e) = fs::write(file, formatted) {
+            error_handler::print_error(&format!("Failed to write file {} {}", name, e));
+            ok = false;
+        } else {
+            println!("Formatted {}", name);
+        }
+    }
+
+    if ok {
+        println!("{}", "Finished".green().bold());
+    } else if check {
+        println!("{}", "Some files are not formatted".red().bold());
+    }
+
+    ok
+}
+
+/// Every .bread file in a folder and the folders inside it
+fn source_files(folder: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(if folder.as_os_str().is_empty() { Path::new(".") } else { folder }) else {
+        return;
+    };
+
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.is_dir() {
+            source_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "bread") {
+            files.push(path);
+        }
+    }
+}
+
+/// Builds the special program named at the start of the arguments, returning it along with how many arguments were used.
+/// Returns None if the arguments don't start with one
+fn special_program(arguments: &[String]) -> Option<Result<(Vec<u8>, usize), ()>> {
+    match arguments.first()?.as_str() {
+        "--display" => Some(Ok((special_programs::segment_display(), 1))),
+        "--brain" => {
+            let Some(input) = arguments.get(1) else {
+                usage();
+                return Some(Err(()));
+            };
+
+            let byte_select = match input.trim().parse::<u8>() {
+                Ok(num) => num,
+                Err(_) => {
+                    error_handler::print_error("Invalid number");
+                    return Some(Err(()));
+                }
+            };
+
+            match special_programs::brain::get_program(byte_select) {
+                Ok(p) => Some(Ok((p, 2))),
+                Err(msg) => {
+                    error_handler::print_error(&msg);
+                    Some(Err(()))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The manifest's options with the command line arguments on top
+fn compile_options(manifest: &Manifest, arguments: &[String]) -> Result<CompileOptions, ()> {
+    let mut options = manifest.compile_options();
+    let mut arguments = arguments.iter();
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--listing" => options.listing = true,
+            "-O" => options.optimize = true,
+            "-D" => {
+                if let Some(define) = arguments.next() {
+                    options.defines.push(define.clone());
+                }
+            }
+            "-I" => {
+                if let Some(dir) = arguments.next() {
+                    options.include_dirs.push(dir.clone());
+                }
+            }
+            "--format" => {
+                if let Some(format) = arguments.next() {
+                    options.format = format.clone();
+                }
+            }
+            "--rom-size" => {
+                match arguments.next().and_then(|size| parse_number(size)).and_then(|size| usize::try_from(size).ok()) {
+                    Some(size) => options.rom_size = size,
+                    None => {
+                        error_handler::print_error("--rom-size needs a number of bytes");
+                        return Err(());
+                    }
+                }
+            }
+            "--ram" => {
+                let range = arguments.next().and_then(|range| range.split_once('-')).and_then(|(start, end)| {
+                    let start = parse_number(start).and_then(|n| usize::try_from(n).ok())?;
+                    let end = parse_number(end).and_then(|n| usize::try_from(n).ok())?;
+                    Some((start, end))
+                });
+                match range {
+                    Some((start, end)) => (options.ram_start, options.ram_end) = (start, end),
+                    None => {
+                        error_handler::print_error("--ram needs the first and last address, such as 0x0100-0x7FFF");
+                        return Err(());
+                    }
+                }
+            }
+            "--fill" => {
+                let fill = arguments.next().and_then(|fill| parse_number(fill)).and_then(|fill| u8::try_from(fill).ok());
+                match fill {
+                    Some(fill) => options.fill = fill,
+                    None => {
+                        error_handler::print_error("--fill needs a byte such as 0xFF");
+                        return Err(());
+                    }
+                }
+            }
+            _ => {
+                if let Some(define) = argument.strip_prefix("-D") {
+                    options.defines.push(define.to_string());
+                } else if let Some(dir) = argument.strip_prefix("-I") {
+                    options.include_dirs.push(dir.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+/// Compiles the project, adding every source file that was read to `files`
+fn build(options: &CompileOptions, files: &mut Vec<PathBuf>) -> Result<Vec<u8>, ()> {
+    println!("Compiling {}", options.name);
+    let bytecode = compile(options, files);
 
     if let Ok(bytecode) = bytecode {
         println!("{}", "Finished".green().bold());
@@ -143,8 +393,12 @@ fn build() -> Result<Vec<u8>, ()> {
 
 fn usage() {
     println!("Usage:");
-    println!("  BreadLang run [--debug]");
-    println!("  BreadLang build");
+    println!("  BreadLang run [--debug] [--watch] [--listing] [-O] [-D NAME[=VALUE]] [-I DIR] [--rom-size BYTES] [--ram START-END]");
+    println!("  BreadLang build [--watch] [--listing] [-O] [-D NAME[=VALUE]] [-I DIR] [--rom-size BYTES] [--ram START-END] [--format FORMAT] [--fill BYTE]");
     println!("  BreadLang new");
-    println!("  BreadLang upload");
+    println!("  BreadLang upload [file | --display | --brain <byte> | [--listing] [-O] [-D NAME[=VALUE]] [-I DIR] [--rom-size BYTES] [--ram START-END]]");
+    println!("  BreadLang export (--display | --brain <byte>) <output> [--format FORMAT] [--fill BYTE]");
+    println!("  BreadLang disasm <file> [output]");
+    println!("  BreadLang fmt [--check] [files]");
+    println!("  BreadLang lsp");
 }
\ No newline at end of file
@@ -0,0 +1,128 @@
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use breadlang::compiling::error_handler;
+
+use crate::project::CompileOptions;
+
+pub const MANIFEST_FILE: &str = "Bread.toml";
+
+/// Project settings read from Bread.toml
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    pub project: ProjectSection,
+    pub build: BuildSection,
+    pub upload: UploadSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectSection {
+    pub name: Option<String>,
+    /// the file containing the main subroutine
+    pub entry: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildSection {
+    pub output: Option<String>,
+    pub format: Option<String>,
+    /// the byte padded formats fill the rest of the ROM with
+    pub fill: Option<u8>,
+    /// how many bytes the ROM can hold
+    pub rom_size: Option<usize>,
+    /// the first and last RAM address variables can be put at
+    pub ram_start: Option<usize>,
+    pub ram_end: Option<usize>,
+    /// the same as -O
+    pub optimize: bool,
+    pub include_dirs: Vec<String>,
+    /// NAME or NAME=VALUE, the same as -D
+    pub defines: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadSection {
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+}
+
+impl Manifest {
+    /// Reads Bread.toml from the current directory, printing any problems. Projects without one get the defaults
+    pub fn load() -> Result<Manifest, ()> {
+        Manifest::read(Path::new("")).map_err(|msg| error_handler::print_error(&msg))
+    }
+
+    /// Reads Bread.toml from a folder. Projects without one get the defaults
+    pub fn read(folder: &Path) -> Result<Manifest, String> {
+        let path = folder.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", MANIFEST_FILE, e))?;
+
+        toml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE, e.message()))
+    }
+
+    /// The compile options described by the manifest, before any command line arguments
+    pub fn compile_options(&self) -> CompileOptions {
+        let mut options = CompileOptions::default();
+
+        if let Some(name) = &self.project.name {
+            options.name = name.clone();
+        }
+        if let Some(entry) = &self.project.entry {
+            options.entry = entry.clone();
+        }
+        if let Some(output) = &self.build.output {
+            options.output = output.clone();
+        }
+        if let Some(format) = &self.build.format {
+            options.format = format.clone();
+        }
+        if let Some(fill) = self.build.fill {
+            options.fill = fill;
+        }
+        if let Some(rom_size) = self.build.rom_size {
+            options.rom_size = rom_size;
+        }
+        if let Some(ram_start) = self.build.ram_start {
+            options.ram_start = ram_start;
+        }
+        if let Some(ram_end) = self.build.ram_end {
+            options.ram_end = ram_end;
+        }
+        options.optimize = self.build.optimize;
+        options.include_dirs = self.build.include_dirs.clone();
+        options.defines = self.build.defines.clone();
+
+        options
+    }
+}
+
+/// The manifest written by `BreadLang new`
+pub fn default_manifest(name: &str) -> String {
+    format!(r#"[project]
+name = "{}"
+entry = "src/main.bread"
+
+[build]
+output = "bin/program.crumbs"
+format = "binary"
+rom_size = 65536
+ram_start = 0x0100
+ram_end = 0xFFFF
+optimize = false
+include_dirs = []
+defines = []
+
+[upload]
+baud = 9600
+"#, name)
+}
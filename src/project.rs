@@ -0,0 +1,197 @@
+use std::{cell::RefCell, env, fs::{self, DirBuilder}, path::{Path, PathBuf}};
+
+use breadlang::{compiling::{compile_sources, error_handler, parse_define, sources::{FileSystem, SourceProvider}, symbols::Allocation, Settings, ADDRESS_SPACE, STACK_SIZE}, formats::Format};
+
+pub struct CompileOptions {
+    /// Name of the project, shown while compiling
+    pub name: String,
+    /// The file containing the main subroutine
+    pub entry: String,
+    /// Where the program is written. The symbol file and listing go next to it
+    pub output: String,
+    /// binary, ihex, srec, logisim, padded or the name of an EEPROM chip such as 28c256
+    pub format: String,
+    /// The byte padded formats fill the rest of the ROM with
+    pub fill: u8,
+    /// How many bytes the ROM can hold
+    pub rom_size: usize,
+    /// The first and last RAM address variables can be put at
+    pub ram_start: usize,
+    pub ram_end: usize,
+    /// Also write a listing next to the output
+    pub listing: bool,
+    /// Run the peephole optimizer
+    pub optimize: bool,
+    /// NAME or NAME=VALUE pairs from the command line
+    pub defines: Vec<String>,
+    /// Extra directories to look for includes in, relative to the project
+    pub include_dirs: Vec<String>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions {
+            name: String::from("project"),
+            entry: String::from("src/main.bread"),
+            output: String::from("bin/program.crumbs"),
+            format: String::from("binary"),
+            fill: 0xFF,
+            rom_size: ADDRESS_SPACE,
+            ram_start: STACK_SIZE,
+            ram_end: ADDRESS_SPACE - 1,
+            listing: false,
+            optimize: false,
+            defines: Vec::new(),
+            include_dirs: Vec::new(),
+        }
+    }
+}
+
+/// Turns the options of the project in `root` into compiler settings
+pub fn settings(root: &Path, options: &CompileOptions) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+
+    if options.rom_size == 0 || options.rom_size > ADDRESS_SPACE {
+        return Err(format!("The ROM size must be between 1 and {} bytes", ADDRESS_SPACE));
+    }
+    settings.rom_size = options.rom_size;
+
+    if options.ram_start < STACK_SIZE {
+        return Err(format!("Variables can't start below 0x{:04X}, the stack uses the RAM before it", STACK_SIZE));
+    }
+    if options.ram_start > options.ram_end || options.ram_end >= ADDRESS_SPACE {
+        return Err(format!("The RAM for variables must be between 0x{:04X} and 0x{:04X}", STACK_SIZE, ADDRESS_SPACE - 1));
+    }
+    settings.ram = options.ram_start as u16..=options.ram_end as u16;
+    settings.optimize = options.optimize;
+
+    for define in &options.defines {
+        let (name, value) = parse_define(define).ok_or(format!("Invalid define {}", define))?;
+        settings.defines.insert(name, value);
+    }
+
+    // include directories are given relative to the project, but files are named relative to the entry file
+    for dir in &options.include_dirs {
+        let path: PathBuf = root.join(dir);
+        if !path.is_dir() {
+            return Err(format!("Include directory {} does not exist", dir));
+        }
+
+        settings.include_dirs.push(path);
+    }
+
+    Ok(settings)
+}
+
+/// Remembers every file that was read through it
+struct Recorder<'a> {
+    sources: &'a FileSystem,
+    read: RefCell<Vec<PathBuf>>,
+}
+
+impl SourceProvider for Recorder<'_> {
+    fn read(&self, path: &Path) -> Option<String> {
+        let contents = self.sources.read(path);
+        if contents.is_some() {
+            self.read.borrow_mut().push(path.to_path_buf());
+        }
+        contents
+    }
+
+    fn canonical(&self, path: &Path) -> PathBuf {
+        self.sources.canonical(path)
+    }
+}
+
+/// Compiles the project, printing any errors and writing the program, symbol file and listing.
+/// `files` gets every source file that was read, even if the program failed to compile
+pub fn compile(options: &CompileOptions, files: &mut Vec<PathBuf>) -> Result<Vec<u8>,()> {
+    let root = env::current_dir().unwrap();
+    let settings = settings(&root, options).map_err(|msg| error_handler::print_error(&msg))?;
+
+    let format = match Format::from_name(&options.format, options.rom_size) {
+        Ok(format) => format,
+        Err(msg) => {
+            error_handler::print_error(&msg);
+            return Err(());
+        }
+    };
+
+    let entry = Path::new(&options.entry);
+
+    if !entry.is_file() {
+        error_handler::print_error(&format!("{} does not exist", options.entry));
+        return Err(());
+    }
+
+    // files are named relative to the folder the entry file is in
+    let folder = entry.parent().unwrap_or(Path::new(""));
+    let sources = FileSystem::new(&root.join(folder));
+    let file = entry.file_name().unwrap().to_str().unwrap();
+
+    let recorder = Recorder { sources: &sources, read: RefCell::new(Vec::new()) };
+    let program = compile_sources(file, &recorder, &settings);
+    files.extend(recorder.read.take().into_iter().map(|path| folder.join(path)));
+
+    let program = match program {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors {
+                error.print(&sources);
+            }
+            return Err(());
+        }
+    };
+
+    for warning in &program.warnings {
+        warning.print(&sources);
+    }
+
+    let output = Path::new(&options.output);
+    if let Some(folder) = output.parent().filter(|f| !f.as_os_str().is_empty()) {
+        DirBuilder::new().recursive(true).create(folder).unwrap();
+    }
+
+    let image = match format.write(&program.bytes, options.fill) {
+        Ok(image) => image,
+        Err(msg) => {
+            error_handler::print_error(&msg);
+            return Err(());
+        }
+    };
+
+    fs::write(output, image).unwrap();
+    fs::write(output.with_extension("sym"), program.symbol_file()).unwrap();
+
+    if options.listing {
+        fs::write(output.with_extension("lst"), program.listing_file()).unwrap();
+    }
+
+    print_size(program.bytes.len(), options.rom_size);
+    print_variables(&program.variables);
+
+    Ok(program.bytes)
+}
+
+fn print_size(used: usize, rom_size: usize) {
+    let percent = used as f64 / rom_size as f64 * 100.0;
+    println!("Size: {} bytes used, {} bytes free ({:.1}% of {})", used, rom_size - used, percent, rom_size);
+}
+
+/// Prints where each variable is in RAM, lowest address first
+fn print_variables(variables: &[Allocation]) {
+    if variables.is_empty() {
+        return;
+    }
+
+    let used: usize = variables.iter().map(|v| v.size as usize).sum();
+    println!("RAM: {} bytes used by {} variables", used, variables.len());
+
+    let mut variables: Vec<&Allocation> = variables.iter().collect();
+    variables.sort_by_key(|v| v.address);
+
+    for variable in variables {
+        let end = variable.address + (variable.size - 1);
+        println!("  0x{:04X}-0x{:04X} {:>5}  {}", variable.address, end, variable.size, variable.name);
+    }
+}
@@ -10,7 +10,9 @@ pub struct State {
     stack_pointer: u8,
     memory: HashMap<u16, u8>,
     rom: Vec<u8>,
-    overflow: bool
+    overflow: bool,
+    /// every value given to OUT, in order
+    outputs: Vec<u8>
 }
 
 impl State {
@@ -33,15 +35,42 @@ impl State {
             0
         }
     }
+
+    pub fn register(&self, register: Register) -> u8 {
+        self.registers[&register]
+    }
+
+    pub fn memory(&self, address: u16) -> u8 {
+        self.get_memory(address)
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn carry(&self) -> bool {
+        self.overflow
+    }
+
+    pub fn outputs(&self) -> &[u8] {
+        &self.outputs
+    }
 }
 
 pub fn run(rom: Vec<u8>, debug: bool) {
+    run_until(rom, debug, &mut || false);
+}
+
+/// Runs the program until it halts or `stop` returns true, which is checked before every instruction.
+/// Returns the state of the machine when it stopped
+pub fn run_until(rom: Vec<u8>, debug: bool, stop: &mut dyn FnMut() -> bool) -> State {
     let mut state = State {
         registers: HashMap::new(),
         program_counter: 0,
         stack_pointer: 0,
         memory: HashMap::new(),
         overflow: false,
+        outputs: Vec::new(),
         rom
     };
     state.registers.insert(Register::A, 0);
@@ -50,6 +79,10 @@ pub fn run(rom: Vec<u8>, debug: bool) {
     state.registers.insert(Register::L, 0);
 
     'main: loop {
+        if stop() {
+            break 'main;
+        }
+
         let byte = state.get_byte();
         state.increment();
     
@@ -240,6 +273,7 @@ pub fn run(rom: Vec<u8>, debug: bool) {
                 if !debug {
                     println!("OUT {}", value);
                 }
+                state.outputs.push(value);
             },
             Instruction::HLT => {
                 if debug {
@@ -258,6 +292,8 @@ pub fn run(rom: Vec<u8>, debug: bool) {
             let _ = stdin().lock().read_line(&mut String::new()); // wait until enter is pressed
         }
     }
+
+    state
 }
 
 fn get_word16(state: &mut State, immediate: bool) -> u16 {
@@ -3,7 +3,7 @@ use std::{io::{stdin, stdout, Write}, thread::sleep, time::{Duration, SystemTime
 use colored::Colorize;
 use serialport::{available_ports, SerialPort, SerialPortInfo};
 
-use crate::compiling::error_handler;
+use breadlang::compiling::error_handler;
 
 #[derive(Copy, Clone)]
 enum ArduinoCommand {
@@ -14,40 +14,18 @@ enum ArduinoCommand {
     Stop = 4
 }
 
-pub fn upload(program: Vec<u8>) {
-    let mut devices: Vec<SerialPortInfo> = Vec::new();
-    println!("Available Devices:");
-    for p in available_ports().expect("No ports found") {
-        println!("{}: {}", devices.len() + 1, p.port_name);
-        devices.push(p);
-    }
-
-    print!("Select a Device: ");
-    stdout().flush().unwrap();
-
-    let mut buffer = String::new();
-    stdin().read_line(&mut buffer).unwrap();
-
-    let selection: usize;
-
-    if let Ok(num) = buffer.trim().parse::<usize>() {
-        selection = num;
-    } else {
-        error_handler::print_error("Invalid number");
-        return;
-    }
-
-    let device: &SerialPortInfo;
-
-    if let Some(d) = &devices.get(selection - 1) {
-        device = d;
-    } else {
-        error_handler::print_error("That is not an option");
-        return;
-    }
+/// Uploads to the given port, or asks which one to use if there isn't one
+pub fn upload(program: Vec<u8>, port: Option<&str>, baud: u32) {
+    let port_name = match port {
+        Some(port) => port.to_string(),
+        None => match select_port() {
+            Some(port) => port,
+            None => return,
+        }
+    };
 
     println!("{}", "Connecting".black());
-    let mut port = serialport::new(&device.port_name, 9600)
+    let mut port = serialport::new(&port_name, baud)
         .timeout(Duration::from_millis(1000))
         .open().expect("Failed to open port");
 
@@ -128,6 +106,37 @@ pub fn upload(program: Vec<u8>) {
     println!("{}", "Finished".green().bold());
 }
 
+fn select_port() -> Option<String> {
+    let mut devices: Vec<SerialPortInfo> = Vec::new();
+    println!("Available Devices:");
+    for p in available_ports().expect("No ports found") {
+        println!("{}: {}", devices.len() + 1, p.port_name);
+        devices.push(p);
+    }
+
+    print!("Select a Device: ");
+    stdout().flush().unwrap();
+
+    let mut buffer = String::new();
+    stdin().read_line(&mut buffer).unwrap();
+
+    let selection: usize;
+
+    if let Ok(num) = buffer.trim().parse::<usize>() {
+        selection = num;
+    } else {
+        error_handler::print_error("Invalid number");
+        return None;
+    }
+
+    if let Some(d) = &devices.get(selection - 1) {
+        Some(d.port_name.clone())
+    } else {
+        error_handler::print_error("That is not an option");
+        None
+    }
+}
+
 fn write_command(port: &mut Box<dyn SerialPort>, command: ArduinoCommand) {
     port.write(&[command as u8]).expect("Write failed!");
 }
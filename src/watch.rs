@@ -0,0 +1,67 @@
+use std::{fs, path::{Path, PathBuf}, thread::sleep, time::{Duration, Instant, SystemTime}};
+
+use breadlang::run::run_until;
+
+use crate::{build, project::CompileOptions};
+
+/// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The files of a program and when each was last modified
+struct Snapshot {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Snapshot {
+    /// `started` is when the build that read the files started. A file modified since then is left without a time so it counts as changed
+    fn new(files: Vec<PathBuf>, started: SystemTime) -> Snapshot {
+        Snapshot {
+            files: files.into_iter().map(|file| {
+                let modified = modified(&file).filter(|time| *time < started);
+                (file, modified)
+            }).collect(),
+        }
+    }
+
+    fn changed(&self) -> bool {
+        self.files.iter().any(|(file, time)| modified(file) != *time)
+    }
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Builds the project every time one of its files changes, running it after each build if `run` is set.
+/// A program that is still running when a file changes is stopped. Only ends when the process is interrupted
+pub fn watch(options: &CompileOptions, run: bool, debug: bool) {
+    loop {
+        // the files are only known once they have been read, so anything saved after the build started
+        // is a change, even if it was saved before the snapshot was taken
+        let started = SystemTime::now();
+        let mut files = vec![PathBuf::from(&options.entry)];
+        let bytecode = build(options, &mut files);
+
+        let snapshot = Snapshot::new(files, started);
+
+        if let (Ok(bytecode), true) = (bytecode, run) {
+            let mut last_check = Instant::now();
+
+            run_until(bytecode, debug, &mut || {
+                if last_check.elapsed() < POLL_INTERVAL {
+                    return false;
+                }
+                last_check = Instant::now();
+                snapshot.changed()
+            });
+        }
+
+        if !snapshot.changed() {
+            println!("Watching for changes...");
+            while !snapshot.changed() {
+                sleep(POLL_INTERVAL);
+            }
+        }
+        println!();
+    }
+}
@@ -0,0 +1,50 @@
+#![allow(dead_code)]
+
+use std::{collections::HashMap, path::Path};
+
+use breadlang::{compiling::{compile_sources, error_handler::CompilerError, sources::FileSystem, CompiledProgram, Settings}, run::{run_until, State}};
+
+/// Compiles a program held in memory as main.bread, returning the error messages if it fails
+pub fn try_compile(source: &str, settings: &Settings) -> Result<CompiledProgram, Vec<String>> {
+    let sources = HashMap::from([(String::from("main.bread"), source.to_string())]);
+    compile_sources("main.bread", &sources, settings).map_err(|errors| messages(&errors))
+}
+
+pub fn compile(source: &str) -> CompiledProgram {
+    compile_with(source, &Settings::default())
+}
+
+pub fn compile_with(source: &str, settings: &Settings) -> CompiledProgram {
+    try_compile(source, settings).unwrap_or_else(|errors| panic!("failed to compile: {:?}", errors))
+}
+
+/// Compiles one of the projects in the examples folder
+pub fn compile_example(name: &str, settings: &Settings) -> CompiledProgram {
+    let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples").join(name).join("src");
+    compile_sources("main.bread", &FileSystem::new(&folder), settings)
+        .unwrap_or_else(|errors| panic!("failed to compile {}: {:?}", name, messages(&errors)))
+}
+
+pub fn messages(errors: &[CompilerError]) -> Vec<String> {
+    errors.iter().map(|e| e.code().to_string()).collect()
+}
+
+/// How many instructions a test program can run before it is assumed to be stuck
+const MAX_STEPS: usize = 1_000_000;
+
+/// Runs a program in the emulator until it halts
+pub fn run(bytes: &[u8]) -> State {
+    let mut steps = 0;
+    let state = run_until(bytes.to_vec(), false, &mut || {
+        steps += 1;
+        steps > MAX_STEPS
+    });
+
+    assert!(steps <= MAX_STEPS, "the program did not halt");
+    state
+}
+
+/// Compiles and runs a program held in memory
+pub fn run_source(source: &str) -> State {
+    run(&compile(source).bytes)
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use breadlang::{compiling::Settings, disassembler::disassemble};
+
+use common::{compile, compile_example};
+
+#[test]
+fn encodings_the_compiler_cannot_produce_are_raw_bytes() {
+    let bytes = [
+        0x30, // LDA without the immediate bit
+        0x32, // LDA without the immediate bit and with a register
+        0x7C, // HLT with the immediate bit
+        0x79, // HLT with a register
+        0xFC, // NOP with the immediate bit
+        0x2C, // POP with the immediate bit
+    ];
+
+    for byte in bytes {
+        let source = disassemble(&[byte], "test");
+        assert!(source.contains(&format!("raw byte 0x{:02X}", byte)), "0x{:02X} was decoded:\n{}", byte, source);
+    }
+}
+
+#[test]
+fn lda_before_a_jump_through_hl_is_a_label() {
+    let source = disassemble(&[0x34, 0x00, 0x04, 0x38, 0x78], "test");
+    assert!(source.contains("LDA _L0004"), "{}", source);
+    assert!(source.contains("DEF _L0004"), "{}", source);
+
+    // a register mode LDA in front of the jump is not decoded, so it can't be followed
+    let source = disassemble(&[0x30, 0x38, 0x78], "test");
+    assert!(source.contains("raw byte 0x30"), "{}", source);
+}
+
+#[test]
+fn every_instruction_round_trips() {
+    let program = compile("
+main:
+	LW A 0x1234
+	LW B
+	SW H 300
+	SW L
+	MW A 255
+	MW B A
+	PUSH 7
+	PUSH H
+	POP L
+	LDA main
+	JMP main
+	JMP
+	JZ B main
+	JZ A
+	JC main
+	JC
+	ADD A 1
+	ADD A B
+	SUB L 2
+	SUB H A
+	OUT 9
+	OUT B
+	NOP
+	HLT
+");
+
+    let source = disassemble(&program.bytes, "test");
+    assert!(!source.contains("raw byte"), "{}", source);
+    assert_eq!(compile(&source).bytes, program.bytes, "{}", source);
+}
+
+#[test]
+fn examples_round_trip() {
+    for example in ["fib", "test_all", "std_test"] {
+        let program = compile_example(example, &Settings::default());
+
+        let source = disassemble(&program.bytes, example);
+        assert!(!source.contains("raw byte"), "{}:\n{}", example, source);
+        assert_eq!(compile(&source).bytes, program.bytes, "{} compiled differently after disassembling", example);
+    }
+}
+
+#[test]
+fn operands_of_0xff_are_not_padding() {
+    for last in ["OUT 255", "MW A 255", "PUSH 0xFF", "LW A 0x12FF"] {
+        let program = compile(&format!("main:\n\tJMP _x\n\tDEF _x\n\t{}\n", last));
+
+        let source = disassemble(&program.bytes, "test");
+        assert!(!source.contains("raw byte") && !source.contains("padding"), "{}:\n{}", last, source);
+        assert_eq!(compile(&source).bytes, program.bytes, "{} compiled differently after disassembling", last);
+
+        // only the 0xFF after the last instruction is padding
+        let mut padded = program.bytes.clone();
+        padded.extend([0xFF; 3]);
+        let source = disassemble(&padded, "test");
+        assert!(source.contains("3 bytes of 0xFF padding"), "{}:\n{}", last, source);
+        assert_eq!(compile(&source).bytes, program.bytes, "{} compiled differently after disassembling", last);
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::collections::HashMap;
+
+use breadlang::compiling::{compile_sources, error_handler::{CompilerError, ErrorCode}, Settings};
+
+fn errors(source: &str) -> Vec<CompilerError> {
+    let sources = HashMap::from([(String::from("main.bread"), source.to_string())]);
+    match compile_sources("main.bread", &sources, &Settings::default()) {
+        Ok(_) => panic!("the program should not compile"),
+        Err(errors) => errors,
+    }
+}
+
+/// The line and column of the error and of its "first defined here" note
+fn duplicate(source: &str, name: &str) -> ((i32, i32), (i32, i32)) {
+    let errors = errors(source);
+    let error = errors.iter()
+        .find(|e| matches!(e.code(), ErrorCode::DuplicateSymbol(n) if n == name))
+        .unwrap_or_else(|| panic!("no duplicate error for {}: {:?}", name, common::messages(&errors)));
+
+    let note = error.notes()[0].location.clone().expect("the note should point at the first definition");
+    ((error.location().line, error.location().column), (note.line, note.column))
+}
+
+#[test]
+fn the_second_main_is_the_duplicate() {
+    let source = "helper:\n\tRET\n\nmain:\n\tHLT\n\nmain:\n\tHLT\n";
+    assert_eq!(duplicate(source, "main"), ((7, 1), (4, 1)));
+}
+
+#[test]
+fn definitions_are_compared_in_source_order() {
+    let source = "const X 1\n\nmain:\n\tHLT\n\nX:\n\tRET\n";
+    assert_eq!(duplicate(source, "X"), ((6, 1), (1, 7)));
+}
+
+#[test]
+fn duplicate_labels_point_at_the_name() {
+    let source = "main:\n\tDEF loop\n\tNOP\n\tDEF loop\n\tJMP loop\n";
+    assert_eq!(duplicate(source, "loop"), ((4, 6), (2, 6)));
+}
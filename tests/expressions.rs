@@ -0,0 +1,19 @@
+mod common;
+
+use breadlang::compiling::Settings;
+
+use common::{compile, try_compile};
+
+#[test]
+fn negating_the_smallest_number_overflows() {
+    let source = "const C -(0-2147483647-1)\n\nmain:\n\tHLT\n";
+
+    let errors = try_compile(source, &Settings::default()).err().expect("the program should not compile");
+    assert!(errors.iter().any(|e| e.starts_with("Expression overflowed")), "{:?}", errors);
+}
+
+#[test]
+fn negative_expressions() {
+    let program = compile("const C -(2 - 7)\n\nmain:\n\tMW A -C\n\tMW B -(-128)\n\tHLT\n");
+    assert_eq!(program.bytes, [0x1C, 0xFB, 0x1D, 0x80, 0x78]);
+}
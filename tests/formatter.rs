@@ -0,0 +1,58 @@
+mod common;
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use breadlang::compiling::{compile_sources, formatter::format, Settings};
+
+use common::{compile_example, messages};
+
+const EXAMPLES: [&str; 3] = ["fib", "test_all", "std_test"];
+
+/// The examples and the standard library
+fn source_files() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut folders: Vec<PathBuf> = vec![root.join("src/compiling/std")];
+    for example in fs::read_dir(root.join("examples")).unwrap() {
+        folders.push(example.unwrap().path().join("src"));
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for folder in folders {
+        for file in fs::read_dir(folder).unwrap() {
+            let path = file.unwrap().path();
+            if path.extension().is_some_and(|e| e == "bread") {
+                files.push(path);
+            }
+        }
+    }
+
+    assert!(files.len() >= 8, "only found {:?}", files);
+    files
+}
+
+#[test]
+fn formatting_keeps_the_code() {
+    // format gives an error instead of changing the code
+    for file in source_files() {
+        let name = file.to_string_lossy().to_string();
+        let text = fs::read_to_string(&file).unwrap();
+
+        let formatted = format(&text, &name).unwrap_or_else(|errors| panic!("failed to format {}: {:?}", name, messages(&errors)));
+        assert_eq!(format(&formatted, &name).ok().as_ref(), Some(&formatted), "formatting {} twice changed it", name);
+    }
+}
+
+#[test]
+fn formatted_examples_compile_the_same() {
+    for example in EXAMPLES {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples").join(example).join("src/main.bread");
+        let formatted = format(&fs::read_to_string(file).unwrap(), "main.bread")
+            .unwrap_or_else(|errors| panic!("failed to format {}: {:?}", example, messages(&errors)));
+
+        let sources = HashMap::from([(String::from("main.bread"), formatted)]);
+        let program = compile_sources("main.bread", &sources, &Settings::default())
+            .unwrap_or_else(|errors| panic!("{} doesn't compile once formatted: {:?}", example, messages(&errors)));
+
+        assert_eq!(program.bytes, compile_example(example, &Settings::default()).bytes, "{} compiled differently once formatted", example);
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use breadlang::compiling::symbols::SymbolKind;
+
+use common::{compile, run};
+
+/// Two subroutines with a loop each, both using `.loop` and `.done`
+const LOCAL_LABELS: &str = "main:
+	MW A 3
+	DEF .loop
+	CALL count
+	SUB A 1
+	JZ A .done
+	JMP .loop
+	DEF .done
+	HLT
+
+; outputs A
+count:
+	MW B A
+	DEF .loop
+	SUB B 1
+	JZ B .done
+	JMP .loop
+	DEF .done
+	OUT A
+	RET
+";
+
+#[test]
+fn local_labels_belong_to_their_subroutine() {
+    let program = compile(LOCAL_LABELS);
+
+    let labels: Vec<(&str, u16)> = program.symbols.iter()
+        .filter(|s| s.kind == SymbolKind::Label)
+        .map(|s| (s.name.as_str(), s.value))
+        .collect();
+    assert_eq!(labels, [("main.loop", 2), ("main.done", 17), ("count.loop", 20), ("count.done", 28)]);
+
+    // each JMP .loop goes back to the loop in its own subroutine
+    assert_eq!(run(&program.bytes).outputs(), [3, 2, 1]);
+}
+
+#[test]
+fn local_labels_can_be_used_from_other_subroutines() {
+    let program = compile(&format!("{}\nafter:\n\tLDA count.done\n\tJMP main.done\n", LOCAL_LABELS));
+
+    // LDA 28, then JMP 17
+    assert_eq!(program.bytes[32..], [0x34, 0x00, 28, 0x3C, 0x00, 17]);
+}
+
+#[test]
+fn symbol_file_and_listing_have_the_qualified_names() {
+    let program = compile(LOCAL_LABELS);
+
+    let symbol_file = program.symbol_file();
+    for line in [
+        "symbol 0x0000 subroutine main main.bread:1",
+        "symbol 0x0002 label main.loop main.bread:3",
+        "symbol 0x0011 label main.done main.bread:8",
+        "symbol 0x0012 subroutine count main.bread:12",
+        "symbol 0x0014 label count.loop main.bread:14",
+        "symbol 0x001C label count.done main.bread:18",
+        // the CALL on line 4 takes 7 bytes
+        "line 0x0002 7 main.bread:4",
+    ] {
+        assert!(symbol_file.lines().any(|l| l == line), "{} is missing from\n{}", line, symbol_file);
+    }
+
+    let listing = program.listing_file();
+    for line in ["0002  DEF main.loop", "0011  DEF main.done", "0014  DEF count.loop", "001C  DEF count.done"] {
+        assert!(listing.lines().any(|l| l == line), "{} is missing from\n{}", line, listing);
+    }
+}
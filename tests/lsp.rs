@@ -0,0 +1,166 @@
+use std::{env, io::{BufRead, BufReader, Write}, path::Path, process::{Command, Stdio}};
+
+use breadlang::compiling::sources::normalize;
+use serde_json::{json, Value};
+
+const BROKEN: &str = "main:\n\tJMP nowhere\n";
+
+const FIXED: &str = "const LIMIT 3
+
+main:
+	MW A LIMIT
+	DEF loop
+	SUB A 1
+	JZ A done
+	JMP loop
+	DEF done
+	HLT
+";
+
+/// Runs `BreadLang lsp` with the messages as its input and returns everything it sent back
+fn session(messages: &[Value]) -> Vec<Value> {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_breadlang"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start the language server");
+
+    let mut input = server.stdin.take().unwrap();
+    for message in messages {
+        let body = message.to_string();
+        write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+    drop(input);
+
+    let mut output = BufReader::new(server.stdout.take().unwrap());
+    let mut replies: Vec<Value> = Vec::new();
+    while let Some(reply) = read_message(&mut output) {
+        replies.push(reply);
+    }
+
+    assert!(server.wait().unwrap().success());
+    replies
+}
+
+fn read_message(output: &mut dyn BufRead) -> Option<Value> {
+    let mut length: Option<usize> = None;
+
+    loop {
+        let mut header = String::new();
+        if output.read_line(&mut header).unwrap() == 0 {
+            return None;
+        }
+
+        match header.trim().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => length = value.trim().parse().ok(),
+            _ if header.trim().is_empty() && length.is_some() => break,
+            _ => (),
+        }
+    }
+
+    let mut body = vec![0; length.unwrap()];
+    output.read_exact(&mut body).unwrap();
+    Some(serde_json::from_slice(&body).unwrap())
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri += &format!("%{:02X}", b);
+        }
+    }
+    uri
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn position(uri: &str, line: u32, character: u32) -> Value {
+    json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end }
+    })
+}
+
+fn response(replies: &[Value], id: u64) -> &Value {
+    replies.iter().find(|r| r["id"] == id).unwrap_or_else(|| panic!("no response to {}", id))
+}
+
+#[test]
+fn session_over_stdio() {
+    // the document is only ever sent to the server, so it doesn't have to exist
+    let path = normalize(&env::temp_dir().join("breadlang-lsp-test").join("main.bread"));
+    let uri = path_to_uri(&path);
+
+    let replies = session(&[
+        request(1, "initialize", json!({ "capabilities": {} })),
+        notification("initialized", json!({})),
+        notification("textDocument/didOpen", json!({
+            "textDocument": { "uri": uri, "languageId": "breadlang", "version": 1, "text": BROKEN }
+        })),
+        notification("textDocument/didChange", json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": FIXED }]
+        })),
+        request(2, "textDocument/definition", position(&uri, 7, 6)),
+        request(3, "textDocument/hover", position(&uri, 7, 6)),
+        request(4, "textDocument/hover", position(&uri, 3, 8)),
+        request(5, "textDocument/completion", position(&uri, 5, 1)),
+        request(6, "textDocument/unknown", json!({})),
+        request(7, "shutdown", Value::Null),
+        notification("exit", Value::Null),
+        // nothing after exit is answered
+        request(8, "shutdown", Value::Null),
+    ]);
+
+    let capabilities = &response(&replies, 1)["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["hoverProvider"], true);
+
+    // the broken document gets an error and the fixed one clears it
+    let published: Vec<&Value> = replies.iter()
+        .filter(|r| r["method"] == "textDocument/publishDiagnostics")
+        .map(|r| &r["params"])
+        .collect();
+    assert_eq!(published.len(), 2, "{:?}", published);
+    assert_eq!(published[0]["uri"], uri);
+    assert_eq!(published[0]["diagnostics"], json!([{
+        "range": range(1, 5, 12),
+        "severity": 1,
+        "source": "BreadLang",
+        "message": "nowhere is not defined",
+        "relatedInformation": []
+    }]));
+    assert_eq!(*published[1], json!({ "uri": uri, "diagnostics": [] }));
+
+    assert_eq!(response(&replies, 2)["result"], json!({ "uri": uri, "range": range(4, 5, 9) }));
+
+    let hover = response(&replies, 3)["result"]["contents"]["value"].as_str().unwrap();
+    assert_eq!(hover, "```breadlang\nDEF loop\n```\nlabel at `0x0002`\n");
+    let hover = response(&replies, 4)["result"]["contents"]["value"].as_str().unwrap();
+    assert_eq!(hover, "```breadlang\nconst LIMIT 3\n```\nconstant `0x0003` (3)\n");
+
+    let completions = response(&replies, 5)["result"].as_array().unwrap();
+    let labels: Vec<&str> = completions.iter().filter_map(|c| c["label"].as_str()).collect();
+    for expected in ["MW", "CALL", "@macro", "A", "main", "LIMIT", "loop", "done"] {
+        assert!(labels.contains(&expected), "{} is missing from {:?}", expected, labels);
+    }
+
+    assert_eq!(response(&replies, 6)["error"]["code"], -32601);
+    assert_eq!(response(&replies, 7)["result"], Value::Null);
+    assert!(replies.iter().all(|r| r["id"] != 8));
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use breadlang::compiling::Settings;
+
+use common::try_compile;
+
+#[test]
+fn exponential_expansion_is_an_error() {
+    // each macro uses the next one ten times, which would be 10^8 instructions
+    let mut source = String::from("@macro\nm0():\n\tNOP\n\n");
+    for level in 1..=8 {
+        source += &format!("@macro\nm{}():\n", level);
+        for _ in 0..10 {
+            source += &format!("\tm{}\n", level - 1);
+        }
+        source += "\n";
+    }
+    source += "main:\n\tm8\n\tHLT\n";
+
+    let errors = try_compile(&source, &Settings::default()).err().expect("the program should not compile");
+    assert!(errors.iter().any(|e| e.starts_with("Macros expand to more than")), "{:?}", errors);
+}
+
+#[test]
+fn large_expansion_that_fits_compiles() {
+    // 10^4 instructions
+    let mut source = String::from("@macro\nm0():\n\tNOP\n\n");
+    for level in 1..=4 {
+        source += &format!("@macro\nm{}():\n", level);
+        for _ in 0..10 {
+            source += &format!("\tm{}\n", level - 1);
+        }
+        source += "\n";
+    }
+    source += "main:\n\tm4\n\tHLT\n";
+
+    let program = try_compile(&source, &Settings::default()).unwrap();
+    assert_eq!(program.bytes.len(), 10_001);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use breadlang::compiling::{Register, Settings};
+
+use common::{compile_example, compile_with, messages, run};
+
+const REGISTERS: [Register; 4] = [Register::A, Register::B, Register::H, Register::L];
+
+fn optimized() -> Settings {
+    Settings { optimize: true, ..Settings::default() }
+}
+
+#[test]
+fn examples_behave_the_same_when_optimized() {
+    for example in ["fib", "test_all", "std_test"] {
+        let plain = compile_example(example, &Settings::default());
+        let optimized = compile_example(example, &optimized());
+        assert!(optimized.warnings.is_empty(), "{} was not optimized: {:?}", example, messages(&optimized.warnings));
+        assert!(optimized.bytes.len() <= plain.bytes.len(), "{} got bigger", example);
+
+        let (before, after) = (run(&plain.bytes), run(&optimized.bytes));
+        assert!(!before.outputs().is_empty(), "{} has no output", example);
+        assert_eq!(before.outputs(), after.outputs(), "{} outputs something else when optimized", example);
+
+        for register in REGISTERS {
+            assert_eq!(before.register(register), after.register(register), "{} ends with a different {:?}", example, register);
+        }
+    }
+}
+
+#[test]
+fn add_zero_before_jc_is_kept() {
+    // ADD A 0 clears the carry that SUB set, so JC must not jump
+    let source = "main:\n\tMW A 5\n\tSUB A 1\n\tADD A 0\n\tJC _carry\n\tOUT 0\n\tHLT\n\tDEF _carry\n\tOUT 1\n\tHLT\n";
+
+    let plain = compile_with(source, &Settings::default());
+    let optimized = compile_with(source, &optimized());
+    assert_eq!(optimized.bytes, plain.bytes);
+    assert_eq!(run(&optimized.bytes).outputs(), [0]);
+}
+
+#[test]
+fn add_zero_with_unused_carry_is_removed() {
+    let source = "main:\n\tMW A 5\n\tADD A 0\n\tSUB A 1\n\tOUT A\n\tHLT\n";
+
+    let plain = compile_with(source, &Settings::default());
+    let optimized = compile_with(source, &optimized());
+    assert_eq!(optimized.bytes.len(), plain.bytes.len() - 2);
+    assert_eq!(run(&optimized.bytes).outputs(), [4]);
+}
+
+#[test]
+fn jumps_to_numbers_are_not_optimized() {
+    // 0x0006 is the OUT 1 after the PUSH and POP, which optimizing would turn into a MW and move
+    let source = "main:\n\tJMP 0x0006\n\tPUSH 2\n\tPOP A\n\tOUT 1\n\tHLT\n";
+
+    let plain = compile_with(source, &Settings::default());
+    let program = compile_with(source, &optimized());
+    assert_eq!(program.bytes, plain.bytes);
+    assert_eq!(messages(&program.warnings), ["The program was not optimized because this jumps to an address that isn't a label, which optimizing could move"]);
+    assert_eq!(run(&program.bytes).outputs(), [1]);
+
+    // so do calls and constants that aren't labels
+    for jump in ["CALL 0x0004", "JC TARGET", "JZ A main + 3"] {
+        let source = format!("const TARGET 3\n\nmain:\n\t{}\n\tPUSH A\n\tPOP A\n\tHLT\n", jump);
+        assert_eq!(compile_with(&source, &optimized()).warnings.len(), 1, "{}", jump);
+    }
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use breadlang::compiling::Settings;
+
+use common::try_compile;
+
+#[test]
+fn lexer_errors_in_skipped_blocks_are_ignored() {
+    let source = "@if 0\nMW A 0xZZ\n@endif\nmain:\n\tHLT\n";
+
+    let program = try_compile(source, &Settings::default()).unwrap();
+    assert_eq!(program.bytes, [0x78]);
+}
+
+#[test]
+fn lexer_errors_in_assembled_blocks_are_reported() {
+    let source = "@if 1\n\tMW A 0xZZ\n@endif\nmain:\n\tHLT\n";
+
+    let errors = try_compile(source, &Settings::default()).err().expect("the program should not compile");
+    assert!(errors.iter().any(|e| e.starts_with("Invalid Number")), "{:?}", errors);
+}
+
+#[test]
+fn skipped_blocks_are_still_checked_for_nesting() {
+    let source = "@if 0\n\t@if 0xZZ\n@endif\nmain:\n\tHLT\n";
+
+    let errors = try_compile(source, &Settings::default()).err().expect("the program should not compile");
+    assert!(errors.iter().any(|e| e.starts_with("@if is missing a matching @endif")), "{:?}", errors);
+}
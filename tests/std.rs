@@ -0,0 +1,158 @@
+mod common;
+
+use breadlang::{compiling::{Register, Settings}, run::State};
+
+use common::{compile_example, run, run_source};
+
+/// Runs `body` as the main subroutine with the standard library file `library` included
+fn run_with(library: &str, body: &str) -> State {
+    run_source(&format!("@include std/{}\n\nmain:\n{}\tHLT\n", library, body))
+}
+
+fn hl(state: &State) -> u16 {
+    u16::from_be_bytes([state.register(Register::H), state.register(Register::L)])
+}
+
+#[test]
+fn mul8() {
+    let pairs: [(u8, u8); 8] = [(0, 200), (200, 0), (1, 255), (16, 16), (255, 255), (13, 11), (128, 2), (2, 128)];
+
+    for (x, y) in pairs {
+        let state = run_with("math", &format!("\tmul8 {} {}\n", x, y));
+        assert_eq!(hl(&state), x as u16 * y as u16, "mul8 {} {}", x, y);
+    }
+
+    let state = run_with("math", "\tMW A 255\n\tMW B 3\n\tmul8 A B\n");
+    assert_eq!(hl(&state), 765);
+}
+
+#[test]
+fn div8() {
+    let pairs: [(u8, u8); 7] = [(200, 7), (0, 5), (12, 1), (255, 255), (255, 1), (254, 255), (1, 2)];
+
+    for (x, y) in pairs {
+        let state = run_with("math", &format!("\tdiv8 {} {}\n", x, y));
+        assert_eq!(state.register(Register::A), x / y, "div8 {} {}", x, y);
+        assert_eq!(state.register(Register::B), x % y, "div8 {} {}", x, y);
+    }
+
+    let state = run_with("math", "\tdiv8 12 0\n");
+    assert_eq!(state.register(Register::A), 255);
+    assert_eq!(state.register(Register::B), 12);
+
+    let state = run_with("math", "\tMW B 9\n\tMW A 40\n\tdiv8 A B\n");
+    assert_eq!((state.register(Register::A), state.register(Register::B)), (4, 4));
+}
+
+#[test]
+fn compare() {
+    let pairs: [(u8, u8); 6] = [(0, 0), (0, 255), (255, 0), (255, 255), (7, 7), (128, 127)];
+    let macros: [(&str, fn(u8, u8) -> bool); 6] = [
+        ("jeq", |x, y| x == y),
+        ("jne", |x, y| x != y),
+        ("jlt", |x, y| x < y),
+        ("jle", |x, y| x <= y),
+        ("jgt", |x, y| x > y),
+        ("jge", |x, y| x >= y),
+    ];
+
+    for (name, expected) in macros {
+        for (x, y) in pairs {
+            // the registers are checked afterwards to make sure nothing was changed
+            let body = format!("\tMW A {x}\n\tMW B {y}\n\tMW H 40\n\tMW L 50\n\t{name} A B _true\n\tOUT 0\n\tHLT\n\tDEF _true\n\tOUT 1\n");
+            let state = run_with("compare", &body);
+
+            assert_eq!(state.outputs(), [expected(x, y) as u8], "{} {} {}", name, x, y);
+            assert_eq!(state.register(Register::A), x, "{} changed A", name);
+            assert_eq!(state.register(Register::B), y, "{} changed B", name);
+            assert_eq!(hl(&state), 40 << 8 | 50, "{} changed H:L", name);
+        }
+
+        // an immediate on either side
+        let state = run_with("compare", &format!("\tMW A 5\n\t{name} A 5 _true\n\tOUT 0\n\tHLT\n\tDEF _true\n\tOUT 1\n"));
+        assert_eq!(state.outputs(), [expected(5, 5) as u8], "{} A 5", name);
+        let state = run_with("compare", &format!("\tMW A 5\n\t{name} 4 A _true\n\tOUT 0\n\tHLT\n\tDEF _true\n\tOUT 1\n"));
+        assert_eq!(state.outputs(), [expected(4, 5) as u8], "{} 4 A", name);
+    }
+}
+
+#[test]
+fn int16() {
+    let cases: [(u16, &str, u16); 12] = [
+        (0x01FF, "inc16", 0x0200),
+        (0x00FF, "inc16", 0x0100),
+        (0xFFFF, "inc16", 0x0000),
+        (0x0100, "dec16", 0x00FF),
+        (0x0000, "dec16", 0xFFFF),
+        (0x0201, "dec16", 0x0200),
+        (1000, "add16 hi(3000) lo(3000)", 4000),
+        (0x00FF, "add16 0 1", 0x0100),
+        (0xFFFF, "add16 0 1", 0x0000),
+        (4000, "sub16 hi(1) lo(1)", 3999),
+        (0x0100, "sub16 0 1", 0x00FF),
+        (0x0000, "sub16 0 1", 0xFFFF),
+    ];
+
+    for (start, operation, expected) in cases {
+        let state = run_with("int16", &format!("\tLDA {}\n\t{}\n", start, operation));
+        assert_eq!(hl(&state), expected, "0x{:04X} {}", start, operation);
+    }
+
+    // register arguments
+    let state = run_with("int16", "\tMW A 0x12\n\tMW B 0x34\n\tLDA 0x1111\n\tadd16 A B\n");
+    assert_eq!(hl(&state), 0x2345);
+}
+
+#[test]
+fn memory() {
+    let state = run_with("memory", "\tmemfill 0x0200 10 7\n");
+    assert!((0x0200..0x020A).all(|a| state.memory(a) == 7));
+    assert_eq!(state.memory(0x01FF), 0);
+    assert_eq!(state.memory(0x020A), 0);
+
+    // filling across the end of a page
+    let state = run_with("memory", "\tmemfill 0x02F0 255 255\n");
+    assert!((0x02F0..0x02F0 + 255).all(|a| state.memory(a) == 255));
+    assert_eq!(state.memory(0x02F0 + 255), 0);
+
+    let state = run_with("memory", "\tmemfill 0x0200 0 7\n");
+    assert_eq!(state.memory(0x0200), 0);
+
+    let state = run_with("memory", "\tmemfill 0x0200 10 7\n\tMW A 10\n\tmemcopy 0x0200 0x0300 A\n");
+    assert!((0x0300..0x030A).all(|a| state.memory(a) == 7));
+    assert_eq!(state.memory(0x030A), 0);
+
+    let state = run_with("memory", "\tmemfill 0x0200 10 7\n\tmemcopy 0x0200 0x0300 0\n");
+    assert_eq!(state.memory(0x0300), 0);
+
+    // the scratch space can be moved
+    let state = run_source("const STD_SCRATCH 0x4000\n@include std/memory\n\nmain:\n\tmemfill 0x0200 3 9\n\tmemcopy 0x0200 0x0300 3\n\tHLT\n");
+    assert!((0x0300..0x0303).all(|a| state.memory(a) == 9));
+    assert_eq!(state.memory(0x4002), 0x03);
+    assert_eq!(state.memory(0x0100), 0);
+}
+
+#[test]
+fn decimal() {
+    for x in [0u8, 9, 10, 99, 100, 207, 255] {
+        let state = run_with("decimal", &format!("\tdigits {}\n", x));
+        assert_eq!(state.register(Register::H), x / 100, "digits {}", x);
+        assert_eq!(state.register(Register::L), x / 10 % 10, "digits {}", x);
+        assert_eq!(state.register(Register::A), x % 10, "digits {}", x);
+    }
+
+    let cases: [(u8, &[u8]); 7] = [(0, &[0]), (7, &[7]), (10, &[1, 0]), (42, &[4, 2]), (100, &[1, 0, 0]), (105, &[1, 0, 5]), (255, &[2, 5, 5])];
+    for (x, digits) in cases {
+        let state = run_with("decimal", &format!("\tout_decimal {}\n", x));
+        assert_eq!(state.outputs(), digits, "out_decimal {}", x);
+    }
+
+    let state = run_with("decimal", "\tMW B 105\n\tout_decimal B\n");
+    assert_eq!(state.outputs(), [1, 0, 5]);
+}
+
+#[test]
+fn std_test_example() {
+    let program = compile_example("std_test", &Settings::default());
+    assert_eq!(run(&program.bytes).outputs(), [1, 2, 3, 4, 5, 0, 4, 2, 1, 0, 5, 2, 5, 5, 6]);
+}
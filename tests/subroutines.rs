@@ -0,0 +1,73 @@
+mod common;
+
+use breadlang::compiling::Register;
+
+use common::{compile, run_source};
+
+/// The example in the README that calls a subroutine with values on the stack
+fn readme_example() -> String {
+    let readme = include_str!("../README.md");
+    let start = readme.find("```\nmain:").expect("the README should have an example program") + 4;
+    let end = start + readme[start..].find("```").unwrap();
+    readme[start..end].to_string()
+}
+
+#[test]
+fn readme_example_adds() {
+    let state = run_source(&readme_example());
+
+    assert_eq!(state.outputs(), [5]);
+    assert_eq!(state.register(Register::A), 5);
+    assert_eq!(state.register(Register::B), 2);
+    // RET leaves the return address in H:L, right after the 2 PUSHes and the 7 byte CALL
+    assert_eq!((state.register(Register::H), state.register(Register::L)), (0, 11));
+    // everything pushed was popped
+    assert_eq!(state.stack_pointer(), 0);
+    // the return address was pushed back high byte first where 2 and 3 were
+    assert_eq!((state.memory(0x0000), state.memory(0x0001)), (0, 11));
+    assert!(!state.carry());
+}
+
+#[test]
+fn call_and_ret_expand_to_stack_operations() {
+    let program = compile("main:\n\tCALL sub\n\tHLT\n\nsub:\n\tRET\n");
+    let expanded = compile("main:\n\tPUSH 0\n\tPUSH 7\n\tJMP sub\n\tHLT\n\nsub:\n\tPOP L\n\tPOP H\n\tJMP\n");
+
+    assert_eq!(program.bytes, expanded.bytes);
+    assert_eq!(program.bytes.len(), 7 + 1 + 3);
+}
+
+#[test]
+fn call_pushes_the_high_byte_first() {
+    // the subroutine halts with the return address still on the stack
+    let state = run_source("main:\n\tNOP\n\tCALL sub\n\tHLT\n\nsub:\n\tHLT\n");
+
+    assert_eq!(state.stack_pointer(), 2);
+    assert_eq!((state.memory(0x0000), state.memory(0x0001)), (0, 8));
+}
+
+#[test]
+fn call_and_ret_keep_a_b_and_carry() {
+    let state = run_source("
+main:
+	MW A 255
+	MW B 7
+	ADD A 1					; sets carry
+	CALL nothing
+	JC _kept
+	OUT 0
+	HLT
+	DEF _kept
+	OUT 1
+	HLT
+
+nothing:
+	RET
+");
+
+    assert_eq!(state.outputs(), [1]);
+    assert_eq!(state.register(Register::A), 0);
+    assert_eq!(state.register(Register::B), 7);
+    assert!(state.carry());
+    assert_eq!(state.stack_pointer(), 0);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use breadlang::compiling::{Settings, STACK_SIZE};
+
+use common::{compile, compile_with, run_source, try_compile};
+
+/// The address each variable was given, in the order they were declared
+fn addresses(source: &str, settings: &Settings) -> Vec<(String, u16)> {
+    compile_with(source, settings).variables.iter().map(|v| (v.name.clone(), v.address)).collect()
+}
+
+#[test]
+fn variables_stay_clear_of_the_stack() {
+    let source = "@var first\n@var second 16\n\nmain:\n\tHLT\n";
+
+    assert_eq!(addresses(source, &Settings::default()), [
+        (String::from("first"), STACK_SIZE as u16),
+        (String::from("second"), STACK_SIZE as u16 + 1),
+    ]);
+}
+
+#[test]
+fn reserved_ram_is_skipped() {
+    let source = "@reserve 0x0100 4\n@reserve 0x0106 2\n@var a 2\n@var b 3\n@var c\n\nmain:\n\tHLT\n";
+
+    // a fits in the gap between the reserved ranges, b doesn't
+    assert_eq!(addresses(source, &Settings::default()), [
+        (String::from("a"), 0x0104),
+        (String::from("b"), 0x0108),
+        (String::from("c"), 0x010B),
+    ]);
+}
+
+#[test]
+fn variables_can_be_used_as_addresses() {
+    let state = run_source("@var counter\n\nmain:\n\tMW A 42\n\tSW A counter\n\tLW B counter\n\tOUT B\n\tHLT\n");
+
+    assert_eq!(state.outputs(), [42]);
+    assert_eq!(state.memory(STACK_SIZE as u16), 42);
+    assert_eq!(compile("@var counter\n\nmain:\n\tLW A counter\n\tHLT\n").bytes, [0x0C, 0x01, 0x00, 0x78]);
+}
+
+#[test]
+fn full_ram_overflows() {
+    let settings = Settings { ram: 0x0100..=0x010F, ..Settings::default() };
+
+    let errors = try_compile("@var a 10\n@var b 6\n@var c\n\nmain:\n\tHLT\n", &settings).err().expect("c should not fit");
+    assert_eq!(errors, ["Variable c needs 1 bytes but there is no room left in RAM"]);
+
+    // reserved RAM counts too
+    let errors = try_compile("@reserve 0x0108 8\n@var a 10\n\nmain:\n\tHLT\n", &settings).err().expect("a should not fit");
+    assert_eq!(errors, ["Variable a needs 10 bytes but there is no room left in RAM"]);
+
+    assert!(try_compile("@var a 10\n@var b 6\n\nmain:\n\tHLT\n", &settings).is_ok());
+}